@@ -1,49 +1,573 @@
-use crate::{process_csv, CmdExecutor};
+use crate::{
+    process_csv, process_csv_cat, process_csv_dedup, process_csv_diff, process_csv_fake,
+    process_csv_from, process_csv_query, process_csv_show, process_csv_sort, process_csv_split,
+    process_csv_stats, process_csv_validate, CmdExecutor, CsvSchema, Expr, DEFAULT_ROW_GROUP_SIZE,
+};
 
-use super::verify_file;
-use clap::Parser;
-use std::{fmt, str::FromStr};
+use super::{verify_file, verify_path};
+use clap::{ArgAction, ArgGroup, Args, Parser};
+use encoding_rs::Encoding;
+use enum_dispatch::enum_dispatch;
+use std::{
+    fmt,
+    io::{self, IsTerminal},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
 
 #[derive(Debug, Clone, Copy)]
 pub enum OutputFormat {
     Json,
     Yaml,
+    Toml,
+    Ndjson,
+    Xml,
+    Markdown,
+    Xlsx,
+    Parquet,
+    Arrow,
+}
+
+/// 遇到无法转换的行时的处理方式
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum OnError {
+    #[default]
+    Fail,
+    Skip,
+    Collect,
 }
 
 /// 文本格式转换
+/// 不带子命令时将 CSV (或 xlsx 工作簿) 转换为其他格式: rcli csv -i input.csv --format yaml
 #[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct CsvOpts {
-    #[arg(short, long, value_parser = verify_file)]
+    #[command(subcommand)]
+    pub cmd: Option<CsvSubCommand>,
+
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
     pub input: String,
 
+    // "-" 表示写到 stdout
     #[arg(short, long)] // "output.json".into()
     pub output: Option<String>,
 
+    // 覆盖已存在的输出文件
+    #[arg(long)]
+    pub force: bool,
+
     #[arg(long, value_parser = parse_format, default_value = "json")]
     pub format: OutputFormat,
 
-    #[arg(short, long, default_value_t = ',')]
-    pub delimiter: char,
+    // 指定列类型的 schema 文件 (json/yaml), 未声明的列自动推断类型
+    #[arg(long, value_parser = verify_file)]
+    pub schema: Option<String>,
+
+    // 输入为 xlsx/xls/ods 工作簿时读取的 sheet, 名称或从 0 开始的下标, 默认为第一个
+    #[arg(long)]
+    pub sheet: Option<String>,
+
+    // 只用前 N 行推断列类型, 避免把整个文件读入内存
+    #[arg(long, default_value_t = 1000)]
+    pub infer_rows: usize,
+
+    // 按某一列的值分组, 输出 { "Goalkeeper": [...], ... }, 只支持 json/yaml/toml
+    #[arg(long)]
+    pub group_by: Option<String>,
+
+    // fail: 中止; skip: 跳过; collect: 跳过并将行号, 原因和原始文本写到 --error-report
+    #[arg(long, value_parser = parse_on_error, default_value = "fail")]
+    pub on_error: OnError,
+
+    #[arg(long, default_value = "errors.csv")]
+    pub error_report: String,
+
+    // parquet 每个 row group 的行数, arrow 每个 record batch 的行数
+    #[arg(long, default_value_t = DEFAULT_ROW_GROUP_SIZE)]
+    pub row_group_size: usize,
+
+    #[command(flatten)]
+    pub transform: CsvTransformOpts,
+
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvStatsOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    // 每列输出出现次数最多的 N 个值
+    #[arg(long, default_value_t = 5)]
+    pub top: usize,
+
+    // 不指定时输出终端表格
+    #[arg(long, value_parser = parse_format)]
+    pub format: Option<OutputFormat>,
+
+    #[arg(long, default_value_t = 60)]
+    pub max_width: usize,
+
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvValidateOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    // 声明列类型, 必填, 正则和枚举值的 schema 文件 (json/yaml)
+    #[arg(long, value_parser = verify_file)]
+    pub schema: String,
+
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvQueryOpts {
+    // 例如 "SELECT Nationality, count(*) FROM players GROUP BY Nationality"
+    pub sql: String,
+
+    // 查询中用到的表, 格式为 name=path, 只给路径时表名为文件名 (不含扩展名)
+    #[arg(short, long = "table", value_parser = parse_table, required = true)]
+    pub tables: Vec<(String, String)>,
+
+    // 不指定时输出终端表格
+    #[arg(long, value_parser = parse_format)]
+    pub format: Option<OutputFormat>,
+
+    #[arg(long, default_value_t = 30)]
+    pub max_width: usize,
+
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvSortOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    // 覆盖已存在的输出文件, 输出可以和输入是同一个文件
+    #[arg(long)]
+    pub force: bool,
+
+    // 排序的列, 逗号分隔, 例如 -k "Nationality,Kit Number:desc"
+    #[arg(short, long = "key", value_delimiter = ',', value_parser = parse_sort_key, required = true)]
+    pub keys: Vec<SortKey>,
+
+    // 超过该内存 (MB) 后将排好序的部分写入临时文件, 最后归并
+    #[arg(long, default_value_t = 256)]
+    pub max_memory: usize,
+
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SortKey {
+    pub column: String,
+    pub desc: bool,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvDedupOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    // 覆盖已存在的输出文件, 输出可以和输入是同一个文件
+    #[arg(long)]
+    pub force: bool,
+
+    // 判断重复的列, 逗号分隔, 不指定时比较整行
+    #[arg(short, long = "key", value_delimiter = ',')]
+    pub keys: Vec<String>,
+
+    // 输入已按 key 排序时只比较相邻的行, 不需要外部排序
+    #[arg(long)]
+    pub sorted: bool,
+
+    // 未排序的输入通过外部排序去重, 超过该内存 (MB) 后写入临时文件
+    #[arg(long, default_value_t = 256)]
+    pub max_memory: usize,
+
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvCatOpts {
+    #[arg(required = true, value_parser = verify_file)]
+    pub inputs: Vec<String>,
+
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    // 覆盖已存在的输出文件, 输出可以和输入是同一个文件
+    #[arg(long)]
+    pub force: bool,
+
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvDiffOpts {
+    #[arg(value_parser = verify_file)]
+    pub old: String,
+
+    #[arg(value_parser = verify_file)]
+    pub new: String,
+
+    // 用于匹配两个文件中同一行的列, 逗号分隔
+    #[arg(short, long = "key", value_delimiter = ',', required = true)]
+    pub keys: Vec<String>,
+
+    // 不指定时输出带颜色的报告, 支持 json 和 yaml
+    #[arg(long, value_parser = parse_format)]
+    pub format: Option<OutputFormat>,
+
+    #[arg(long)]
+    pub no_color: bool,
+
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+#[derive(Debug, Parser)]
+#[command(group(ArgGroup::new("mode").required(true).args(["rows", "size", "by"])))]
+pub struct CsvSplitOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    // 每个文件最多 N 行 (不含表头)
+    #[arg(long)]
+    pub rows: Option<usize>,
+
+    // 每个文件最大 N MB
+    #[arg(long)]
+    pub size: Option<usize>,
+
+    // 按该列的值拆分, 每个值一个文件
+    #[arg(long)]
+    pub by: Option<String>,
+
+    // 输出文件名模板, 支持 {index}, {index:3} (补零) 和 {value} (--by 的列值),
+    // 默认为 <输入文件名>_{index}.csv 或 <输入文件名>_{value}.csv
+    #[arg(short, long)]
+    pub template: Option<String>,
+
+    // 输出目录
+    #[arg(long, value_parser = verify_path, default_value = ".")]
+    pub dir: PathBuf,
+
+    // 覆盖已存在的输出文件
+    #[arg(long)]
+    pub force: bool,
+
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+/// 在输出之前对每条记录做过滤, 选择列和重命名, 使用的都是原始列名
+#[derive(Debug, Clone, Default, Args)]
+pub struct CsvTransformOpts {
+    // 只输出这些列, 逗号分隔
+    #[arg(long, value_delimiter = ',')]
+    pub select: Vec<String>,
+
+    // 例如 --rename "Kit Number=kit"
+    #[arg(long, value_delimiter = ',', value_parser = parse_rename)]
+    pub rename: Vec<(String, String)>,
+
+    // 例如 --where 'Position == "Goalkeeper" && Kit Number > 20'
+    #[arg(long = "where", value_parser = parse_expr)]
+    pub filter: Option<Expr>,
+
+    // 将 address.city 这样的列名展开为嵌套对象, 与 csv from 的展开相反
+    #[arg(long)]
+    pub nest: bool,
+}
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExecutor)]
+pub enum CsvSubCommand {
+    #[command(about = "Convert an array of JSON/YAML objects or an xlsx sheet back to CSV")]
+    From(CsvFromOpts),
+    #[command(about = "Show CSV as a table in the terminal")]
+    Show(CsvShowOpts),
+    #[command(about = "Profile every column of a CSV file")]
+    Stats(CsvStatsOpts),
+    #[command(about = "Validate every row of a CSV file against a schema")]
+    Validate(CsvValidateOpts),
+    #[command(about = "Run a SQL-like query over one or two CSV files")]
+    Query(CsvQueryOpts),
+    #[command(about = "Sort a CSV file by one or more columns, even if it does not fit in memory")]
+    Sort(CsvSortOpts),
+    #[command(about = "Remove duplicate rows by key columns")]
+    Dedup(CsvDedupOpts),
+    #[command(about = "Concatenate CSV files, merging differing headers")]
+    Cat(CsvCatOpts),
+    #[command(about = "Show added, removed and modified rows between two CSV files")]
+    Diff(CsvDiffOpts),
+    #[command(about = "Split a CSV file into chunks by row count, size or column value")]
+    Split(CsvSplitOpts),
+    #[command(about = "Generate fake CSV rows from a schema")]
+    Fake(CsvFakeOpts),
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvFromOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    #[arg(short, long, default_value = "output.csv")]
+    pub output: String,
+
+    #[arg(long)]
+    pub force: bool,
+
+    // 未指定时根据输入文件的扩展名判断, 默认为 json
+    #[arg(long, value_parser = parse_format)]
+    pub format: Option<OutputFormat>,
+
+    #[arg(short, long, value_parser = parse_csv_byte, default_value = ",")]
+    pub delimiter: u8,
+}
+
+#[derive(Debug, Parser)]
+pub struct CsvShowOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+
+    // 只显示前 N 行
+    #[arg(long, conflicts_with = "tail")]
+    pub head: Option<usize>,
+
+    // 只显示最后 N 行
+    #[arg(long)]
+    pub tail: Option<usize>,
+
+    // 要显示的列, 逗号分隔
+    #[arg(short, long, value_delimiter = ',')]
+    pub columns: Vec<String>,
+
+    // 单元格的最大显示宽度, 0 表示不截断
+    #[arg(long, default_value_t = 30)]
+    pub max_width: usize,
+
+    // 不输出边框, 方便配合 pager 使用
+    #[arg(long)]
+    pub plain: bool,
+
+    #[command(flatten)]
+    pub reader: CsvReaderOpts,
+}
+
+/// 根据 schema 生成测试数据: rcli csv fake --schema players.json --rows 1000000 --seed 42
+#[derive(Debug, Parser)]
+pub struct CsvFakeOpts {
+    // 列的生成方式见 schema 中的 fake 字段
+    #[arg(long, value_parser = verify_file)]
+    pub schema: String,
+
+    #[arg(short, long, default_value_t = 100)]
+    pub rows: usize,
 
-    #[arg(long, default_value_t = true)]
+    // 相同的种子生成相同的数据
+    #[arg(long)]
+    pub seed: Option<u64>,
+
+    #[arg(short, long, default_value = "-")]
+    pub output: String,
+
+    // 覆盖已存在的输出文件
+    #[arg(long)]
+    pub force: bool,
+
+    #[arg(short, long, value_parser = parse_csv_byte, default_value = ",")]
+    pub delimiter: u8,
+}
+
+/// CSV 读取选项, 所有读取 CSV 的子命令共用
+#[derive(Debug, Clone, Args)]
+pub struct CsvReaderOpts {
+    #[arg(short, long, value_parser = parse_csv_byte, default_value = ",")]
+    pub delimiter: u8,
+
+    #[arg(long, value_parser = parse_csv_byte, default_value = "\"")]
+    pub quote: u8,
+
+    // 未指定时使用双引号转义 ("")
+    #[arg(long, value_parser = parse_csv_byte)]
+    pub escape: Option<u8>,
+
+    // 以该字符开头的行会被忽略
+    #[arg(long, value_parser = parse_csv_byte)]
+    pub comment: Option<u8>,
+
+    // 没有表头时字段名为 col1..colN
+    #[arg(long = "no-header", action = ArgAction::SetFalse)]
     pub header: bool,
+
+    // 输入的编码, 例如 gbk, utf-16le, latin1; auto 时根据 BOM 和内容检测
+    #[arg(long, value_parser = parse_encoding, default_value = "auto")]
+    pub encoding: CsvEncoding,
+
+    // 允许列数与表头不同的行, 缺少的列为 null, 多出的列被忽略
+    #[arg(long)]
+    pub flexible: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum CsvEncoding {
+    #[default]
+    Auto,
+    Label(&'static Encoding),
+}
+
+impl Default for CsvReaderOpts {
+    fn default() -> Self {
+        Self {
+            delimiter: b',',
+            quote: b'"',
+            escape: None,
+            comment: None,
+            header: true,
+            encoding: CsvEncoding::Auto,
+            flexible: false,
+        }
+    }
 }
 
 fn parse_format(format: &str) -> Result<OutputFormat, anyhow::Error> {
     format.parse()
 }
 
+fn parse_rename(s: &str) -> Result<(String, String), &'static str> {
+    match s.split_once('=') {
+        Some((from, to)) if !from.is_empty() && !to.is_empty() => {
+            Ok((from.to_string(), to.to_string()))
+        }
+        _ => Err("must be in the form old=new"),
+    }
+}
+
+fn parse_table(s: &str) -> Result<(String, String), String> {
+    let (name, path) = match s.split_once('=') {
+        Some((name, path)) if !name.is_empty() => (name.to_string(), path),
+        _ => {
+            let stem = Path::new(s).file_stem().and_then(|stem| stem.to_str());
+            (stem.unwrap_or_default().to_string(), s)
+        }
+    };
+    let path = verify_file(path)?;
+    if name.is_empty() || path == "-" && !s.contains('=') {
+        return Err("stdin must be named, e.g. players=-".to_string());
+    }
+    Ok((name, path))
+}
+
+fn parse_encoding(s: &str) -> Result<CsvEncoding, anyhow::Error> {
+    s.parse()
+}
+
+fn parse_on_error(s: &str) -> Result<OnError, anyhow::Error> {
+    s.parse()
+}
+
+fn parse_sort_key(s: &str) -> Result<SortKey, anyhow::Error> {
+    s.parse()
+}
+
+fn parse_expr(s: &str) -> Result<Expr, anyhow::Error> {
+    s.parse()
+}
+
+/// csv crate 只接受单字节的分隔符, 支持 "\t" 和 "tab" 方便在 shell 中输入
+fn parse_csv_byte(s: &str) -> Result<u8, &'static str> {
+    match s {
+        "\\t" | "tab" => Ok(b'\t'),
+        _ if s.len() == 1 && s.is_ascii() => Ok(s.as_bytes()[0]),
+        _ => Err("must be a single ASCII character"),
+    }
+}
+
 impl FromStr for OutputFormat {
     type Err = anyhow::Error;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         match s {
             "json" => Ok(OutputFormat::Json),
-            "yaml" => Ok(OutputFormat::Yaml),
+            "yaml" | "yml" => Ok(OutputFormat::Yaml),
+            "toml" => Ok(OutputFormat::Toml),
+            "ndjson" | "jsonl" => Ok(OutputFormat::Ndjson),
+            "xml" => Ok(OutputFormat::Xml),
+            "md" | "markdown" => Ok(OutputFormat::Markdown),
+            "xlsx" => Ok(OutputFormat::Xlsx),
+            "parquet" => Ok(OutputFormat::Parquet),
+            "arrow" | "ipc" | "feather" => Ok(OutputFormat::Arrow),
             _ => Err(anyhow::anyhow!("Invalid format")),
         }
     }
 }
+/// 支持 encoding_rs 的所有标签, latin1 对应 windows-1252
+impl FromStr for CsvEncoding {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("auto") {
+            return Ok(CsvEncoding::Auto);
+        }
+        match Encoding::for_label(s.as_bytes()) {
+            Some(encoding) => Ok(CsvEncoding::Label(encoding)),
+            None => anyhow::bail!("unknown encoding {:?}", s),
+        }
+    }
+}
+
+impl FromStr for OnError {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "fail" => Ok(OnError::Fail),
+            "skip" => Ok(OnError::Skip),
+            "collect" => Ok(OnError::Collect),
+            _ => anyhow::bail!("invalid --on-error {:?}, expected fail, skip or collect", s),
+        }
+    }
+}
+
+/// 列名后可以加 :asc 或 :desc, 默认升序
+impl FromStr for SortKey {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (column, desc) = match s.rsplit_once(':') {
+            Some((column, "desc")) => (column, true),
+            Some((column, "asc")) => (column, false),
+            _ => (s, false),
+        };
+        if column.is_empty() {
+            anyhow::bail!("sort key must be a column name");
+        }
+        Ok(SortKey {
+            column: column.to_string(),
+            desc,
+        })
+    }
+}
+
 /// format!需要参数类型实现fmt::Display trait
 impl fmt::Display for OutputFormat {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -56,18 +580,216 @@ impl From<OutputFormat> for &'static str {
         match value {
             OutputFormat::Json => "json",
             OutputFormat::Yaml => "yaml",
+            OutputFormat::Toml => "toml",
+            OutputFormat::Ndjson => "ndjson",
+            OutputFormat::Xml => "xml",
+            // 同时作为默认输出文件的扩展名
+            OutputFormat::Markdown => "md",
+            OutputFormat::Xlsx => "xlsx",
+            OutputFormat::Parquet => "parquet",
+            OutputFormat::Arrow => "arrow",
         }
     }
 }
 
 impl CmdExecutor for CsvOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let output = if let Some(output) = self.output {
-            output.clone()
-        } else {
-            format!("output.{}", self.format)
+        if let Some(cmd) = self.cmd {
+            return cmd.execute().await;
+        }
+        let skipped = process_csv(&self)?;
+        if skipped > 0 {
+            let report = match self.on_error {
+                OnError::Collect => format!(", see {}", self.error_report),
+                _ => String::new(),
+            };
+            eprintln!("❌ skipped {} bad rows{}", skipped, report);
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for CsvFromOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let format = match self.format {
+            Some(format) => format,
+            None => Path::new(&self.input)
+                .extension()
+                .and_then(|ext| ext.to_str())
+                .and_then(|ext| ext.parse().ok())
+                .unwrap_or(OutputFormat::Json),
+        };
+        process_csv_from(
+            &self.input,
+            &self.output,
+            format,
+            self.delimiter,
+            self.force,
+        )?;
+        Ok(())
+    }
+}
+
+impl CmdExecutor for CsvShowOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let table = process_csv_show(&self)?;
+        println!("{}", table);
+        Ok(())
+    }
+}
+
+impl CmdExecutor for CsvStatsOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let ret = process_csv_stats(&self)?;
+        println!("{}", ret.trim_end());
+        Ok(())
+    }
+}
+
+impl CmdExecutor for CsvValidateOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let schema = CsvSchema::load(&self.schema)?;
+        let report = process_csv_validate(&self.input, &self.reader, &schema)?;
+        if report.violations.is_empty() {
+            println!("✅ {} rows are valid", report.rows);
+            return Ok(());
+        }
+        for violation in &report.violations {
+            println!("❌ {}", violation);
+        }
+        // 返回错误使进程以非零状态退出, 便于在 CI 中使用
+        anyhow::bail!(
+            "{} violations found in {} rows",
+            report.violations.len(),
+            report.rows
+        )
+    }
+}
+
+impl CmdExecutor for CsvQueryOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let ret = process_csv_query(&self)?;
+        println!("{}", ret.trim_end());
+        Ok(())
+    }
+}
+
+impl CmdExecutor for CsvSortOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_sort(&self)
+    }
+}
+
+impl CmdExecutor for CsvDedupOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let removed = process_csv_dedup(&self)?;
+        // 结果可能写到 stdout, 统计信息输出到 stderr
+        eprintln!("removed {} duplicate rows", removed);
+        Ok(())
+    }
+}
+
+impl CmdExecutor for CsvCatOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        process_csv_cat(&self)
+    }
+}
+
+impl CmdExecutor for CsvDiffOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let diff = process_csv_diff(&self)?;
+        let ret = match self.format {
+            Some(OutputFormat::Json) => serde_json::to_string_pretty(&diff)?,
+            Some(OutputFormat::Yaml) => serde_yaml::to_string(&diff)?,
+            Some(format) => anyhow::bail!("csv diff does not support {} output", format),
+            None => {
+                // 重定向到文件时不输出颜色
+                if self.no_color || !io::stdout().is_terminal() {
+                    colored::control::set_override(false);
+                }
+                diff.render(&self.keys)
+            }
         };
-        process_csv(&self.input, output, self.format)?;
+        println!("{}", ret.trim_end());
+        Ok(())
+    }
+}
+
+impl CmdExecutor for CsvSplitOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let paths = process_csv_split(&self)?;
+        for path in &paths {
+            println!("{}", path.display());
+        }
+        eprintln!("✅ split into {} files", paths.len());
+        Ok(())
+    }
+}
+
+impl CmdExecutor for CsvFakeOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let seed = process_csv_fake(&self)?;
+        eprintln!("✅ generated {} rows with seed {}", self.rows, seed);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_csv_byte() {
+        assert_eq!(parse_csv_byte(";"), Ok(b';'));
+        assert_eq!(parse_csv_byte("\\t"), Ok(b'\t'));
+        assert_eq!(parse_csv_byte("tab"), Ok(b'\t'));
+        assert!(parse_csv_byte(";;").is_err());
+        assert!(parse_csv_byte("é").is_err());
+    }
+
+    #[test]
+    fn test_parse_rename() {
+        assert_eq!(
+            parse_rename("Kit Number=kit"),
+            Ok(("Kit Number".to_string(), "kit".to_string()))
+        );
+        assert!(parse_rename("kit").is_err());
+        assert!(parse_rename("=kit").is_err());
+    }
+
+    #[test]
+    fn test_parse_table() {
+        assert_eq!(
+            parse_table("assets/juventus.csv"),
+            Ok(("juventus".to_string(), "assets/juventus.csv".to_string()))
+        );
+        assert_eq!(
+            parse_table("players=assets/juventus.csv"),
+            Ok(("players".to_string(), "assets/juventus.csv".to_string()))
+        );
+        assert!(parse_table("players=not-exist.csv").is_err());
+        assert!(parse_table("-").is_err());
+    }
+
+    #[test]
+    fn test_parse_sort_key() -> anyhow::Result<()> {
+        let key: SortKey = "Kit Number:desc".parse()?;
+        assert_eq!((key.column.as_str(), key.desc), ("Kit Number", true));
+        let key: SortKey = "DOB".parse()?;
+        assert_eq!((key.column.as_str(), key.desc), ("DOB", false));
+        assert!(":desc".parse::<SortKey>().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_output_format_round_trip() {
+        for name in [
+            "json", "yaml", "toml", "ndjson", "xml", "md", "xlsx", "parquet", "arrow",
+        ] {
+            let format: OutputFormat = name.parse().unwrap();
+            assert_eq!(format.to_string(), name);
+        }
+        assert!(matches!("markdown".parse(), Ok(OutputFormat::Markdown)));
+        assert!("csv".parse::<OutputFormat>().is_err());
+    }
+}
@@ -1,37 +1,213 @@
-use crate::{process_genpass, CmdExecutor};
-use clap::Parser;
-use zxcvbn::zxcvbn;
+use std::str::FromStr;
 
-/// 生成密码
+use super::verify_file;
+use crate::{
+    password_entropy, process_genpass, process_genpass_check, process_genphrase, CharClasses,
+    CmdExecutor, PasswordInfo, PasswordPolicy,
+};
+use anyhow::Result;
+use clap::{ArgAction, Args, Parser};
+use enum_dispatch::enum_dispatch;
+
+/// 生成密码, 指定 --words 时生成由单词组成的口令: rcli genpass --words 6 --separator -
 #[derive(Debug, Parser)]
+#[command(args_conflicts_with_subcommands = true)]
 pub struct GenPassOpts {
-    #[arg(short, long, default_value_t = 16)]
-    pub length: u8,
-    #[arg(long, default_value_t = true)]
+    #[command(subcommand)]
+    pub cmd: Option<GenPassSubCommand>,
+
+    // 默认 16, 指定 --policy 时调整到策略允许的长度范围内
+    #[arg(short, long)]
+    pub length: Option<u8>,
+    // 默认包含所有种类的字符, 用 --no-xxx 关闭
+    #[arg(long = "no-uppercase", action = ArgAction::SetFalse)]
     pub uppercase: bool,
-    #[arg(long, default_value_t = true)]
+    #[arg(long = "no-lowercase", action = ArgAction::SetFalse)]
     pub lowercase: bool,
-    #[arg(long, default_value_t = true)]
+    #[arg(long = "no-number", action = ArgAction::SetFalse)]
     pub number: bool,
-    #[arg(long, default_value_t = true)]
+    #[arg(long = "no-symbols", action = ArgAction::SetFalse)]
     pub symbols: bool,
+    // 旧版本的 --uppercase 等参数默认就是打开的, 保留为隐藏参数以兼容已有脚本, 不起作用
+    #[arg(long = "uppercase", hide = true)]
+    compat_uppercase: bool,
+    #[arg(long = "lowercase", hide = true)]
+    compat_lowercase: bool,
+    #[arg(long = "number", hide = true)]
+    compat_number: bool,
+    #[arg(long = "symbols", hide = true)]
+    compat_symbols: bool,
+    // yaml 格式的密码策略, 生成的密码保证满足策略
+    #[arg(long, value_parser = verify_file, conflicts_with = "words")]
+    pub policy: Option<String>,
+
+    #[command(flatten)]
+    pub phrase: PassphraseOpts,
+
+    // 一次生成多个密码
+    #[arg(short, long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub count: u32,
+    #[arg(long, value_parser = parse_genpass_format, default_value = "text")]
+    pub format: GenPassFormat,
+}
+
+#[derive(Debug, Parser)]
+#[enum_dispatch(CmdExecutor)]
+pub enum GenPassSubCommand {
+    #[command(about = "Check the strength of candidate passwords, one per line")]
+    Check(GenPassCheckOpts),
+}
+
+/// 检查密码强度, 有密码低于 --min-score 或违反策略时以非零状态退出
+#[derive(Debug, Parser)]
+pub struct GenPassCheckOpts {
+    #[arg(short, long, value_parser = verify_file, default_value = "-")]
+    pub input: String,
+    #[arg(long, value_parser = verify_file)]
+    pub policy: Option<String>,
+    // zxcvbn 分数 0-4
+    #[arg(long, default_value_t = 3, value_parser = clap::value_parser!(u8).range(0..=4))]
+    pub min_score: u8,
+    #[arg(long, value_parser = parse_genpass_format, default_value = "text")]
+    pub format: GenPassFormat,
+}
+
+/// Diceware 风格的口令选项
+#[derive(Debug, Clone, Args)]
+pub struct PassphraseOpts {
+    // 单词个数
+    #[arg(long, conflicts_with = "length")]
+    pub words: Option<u8>,
+    #[arg(long, default_value = "-", requires = "words")]
+    pub separator: String,
+    // 每行一个单词, 也支持 EFF 词表的格式; 默认使用内置词表
+    #[arg(long, value_parser = verify_file, requires = "words")]
+    pub wordlist: Option<String>,
+    // 每个单词首字母大写
+    #[arg(long, requires = "words")]
+    pub capitalize: bool,
+    // 在随机一个单词后面加一个数字
+    #[arg(long, requires = "words")]
+    pub digit: bool,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum GenPassFormat {
+    Text,
+    Json,
+}
+
+fn parse_genpass_format(format: &str) -> Result<GenPassFormat> {
+    format.parse()
+}
+
+impl FromStr for GenPassFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(GenPassFormat::Text),
+            "json" => Ok(GenPassFormat::Json),
+            _ => Err(anyhow::anyhow!("invalid format")),
+        }
+    }
+}
+
+impl GenPassOpts {
+    fn classes(&self) -> CharClasses {
+        CharClasses {
+            upper: self.uppercase,
+            lower: self.lowercase,
+            number: self.number,
+            symbol: self.symbols,
+        }
+    }
+
+    fn generate(&self, policy: &PasswordPolicy) -> Result<PasswordInfo> {
+        let info = match self.phrase.words {
+            Some(_) => {
+                let ret = process_genphrase(&self.phrase)?;
+                PasswordInfo::new(ret.phrase, ret.entropy_bits)
+            }
+            None => {
+                let length = policy.password_length(self.length, self.classes())?;
+                let ret = process_genpass(length, self.classes(), policy)?;
+                let entropy_bits = password_entropy(length, self.classes(), policy);
+                PasswordInfo::new(ret, entropy_bits)
+            }
+        };
+        Ok(info)
+    }
 }
 
 impl CmdExecutor for GenPassOpts {
     async fn execute(self) -> anyhow::Result<()> {
-        let ret = process_genpass(
-            self.length,
-            self.uppercase,
-            self.lowercase,
-            self.number,
-            self.symbols,
-        )?;
-        // 将打印从通用方法中移出
-        println!("{}", ret);
-
-        let estimate = zxcvbn(&ret, &[]);
-        // eprintln!在pipe时不显示, score()显示密码强度0-4(低-高)
-        eprintln!("Password strength: {}", estimate.score());
+        if let Some(cmd) = self.cmd {
+            return cmd.execute().await;
+        }
+        let policy = match &self.policy {
+            Some(path) => PasswordPolicy::load(path)?,
+            None => PasswordPolicy::default(),
+        };
+        let infos = (0..self.count)
+            .map(|_| self.generate(&policy))
+            .collect::<Result<Vec<_>>>()?;
+        match self.format {
+            // json 总是输出数组, 方便脚本处理
+            GenPassFormat::Json => println!("{}", serde_json::to_string_pretty(&infos)?),
+            GenPassFormat::Text => {
+                // 将打印从通用方法中移出
+                for info in &infos {
+                    println!("{}", info.password);
+                }
+                // eprintln!在pipe时不显示, 只生成一个密码时显示密码强度0-4(低-高)
+                if let [info] = infos.as_slice() {
+                    eprintln!("Password strength: {}", info.zxcvbn_score);
+                    eprintln!("Entropy: {:.1} bits", info.entropy_bits);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl CmdExecutor for GenPassCheckOpts {
+    async fn execute(self) -> anyhow::Result<()> {
+        let checks = process_genpass_check(&self)?;
+        match self.format {
+            GenPassFormat::Json => println!("{}", serde_json::to_string_pretty(&checks)?),
+            GenPassFormat::Text => {
+                for check in &checks {
+                    let mark = if check.passed { "✅" } else { "❌" };
+                    println!(
+                        "{} line {}: score {}/4, {} characters",
+                        mark, check.line, check.zxcvbn_score, check.length
+                    );
+                    let times = &check.crack_times;
+                    println!(
+                        "   crack time: {} (online, throttled), {} (online), {} (offline, slow hash), {} (offline, fast hash)",
+                        times.online_throttling,
+                        times.online_no_throttling,
+                        times.offline_slow_hashing,
+                        times.offline_fast_hashing
+                    );
+                    if let Some(warning) = &check.warning {
+                        println!("   warning: {}", warning);
+                    }
+                    for suggestion in &check.suggestions {
+                        println!("   suggestion: {}", suggestion);
+                    }
+                    for violation in &check.violations {
+                        println!("   policy: {}", violation);
+                    }
+                }
+            }
+        }
+        let failed = checks.iter().filter(|c| !c.passed).count();
+        // 返回错误使进程以非零状态退出, 便于在脚本中使用
+        if failed > 0 {
+            anyhow::bail!("{} of {} passwords failed the check", failed, checks.len());
+        }
         Ok(())
     }
 }
@@ -20,7 +20,7 @@ pub struct Opts {
 #[derive(Debug, Parser)]
 pub enum SubCommand {
     #[command(name = "csv", about = "Show CSV, or convert CSV to other formats.")]
-    Csv(CsvOpts),
+    Csv(Box<CsvOpts>),
     #[command(name = "genpass", about = "Generate a random password")]
     GenPass(GenPassOpts),
     #[command(subcommand, about = "Base64 encode or decode")]
@@ -0,0 +1,327 @@
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, BufWriter, Seek, SeekFrom, Write},
+    sync::Arc,
+};
+
+use anyhow::Result;
+use arrow::{
+    array::{ArrayRef, BooleanBuilder, Float64Builder, Int64Builder, StringBuilder},
+    datatypes::{DataType, Field, Schema, SchemaRef},
+    ipc::writer::FileWriter,
+    record_batch::RecordBatch,
+};
+use parquet::{arrow::ArrowWriter, basic::Compression, file::properties::WriterProperties};
+use serde_json::Value;
+
+use crate::cli::OutputFormat;
+
+use super::csv_writer::{cell_to_string, Record, RecordWriter};
+
+pub const DEFAULT_ROW_GROUP_SIZE: usize = 65536;
+
+/**
+ * 输出 parquet 或 arrow ipc 文件, 每 row_group_size 条记录写出一个 row group (record batch);
+ * 列类型需要在写入之前确定, 所以记录先以 json lines 写入临时文件, 同时根据所有记录推断列类型,
+ * finish 时再读回来写出, 后面出现的新列或不兼容的值不会导致失败
+ */
+pub struct ColumnarWriter<W: Write + Send> {
+    writer: W,
+    format: OutputFormat,
+    row_group_size: usize,
+    spill: BufWriter<File>,
+    columns: Vec<(String, Option<DataType>)>,
+}
+
+enum ColumnarOutput<W: Write + Send> {
+    Parquet(ArrowWriter<W>),
+    Arrow(FileWriter<W>),
+}
+
+impl<W: Write + Send> ColumnarWriter<W> {
+    pub fn try_new(writer: W, format: OutputFormat, row_group_size: usize) -> Result<Self> {
+        if !matches!(format, OutputFormat::Parquet | OutputFormat::Arrow) {
+            anyhow::bail!("{} is not a columnar format", format);
+        }
+        if row_group_size == 0 {
+            anyhow::bail!("row group size must be positive");
+        }
+        Ok(Self {
+            writer,
+            format,
+            row_group_size,
+            spill: BufWriter::new(tempfile::tempfile()?),
+            columns: Vec::new(),
+        })
+    }
+}
+
+impl<W: Write + Send> RecordWriter for ColumnarWriter<W> {
+    fn write_record(&mut self, record: &Record) -> Result<()> {
+        // json 字符串中的换行会被转义, 每条记录正好一行
+        serde_json::to_writer(&mut self.spill, record)?;
+        self.spill.write_all(b"\n")?;
+        merge_column_types(&mut self.columns, record);
+        Ok(())
+    }
+
+    fn finish(self: Box<Self>) -> Result<()> {
+        let Self {
+            writer,
+            format,
+            row_group_size,
+            spill,
+            columns,
+        } = *self;
+        let mut file = spill.into_inner().map_err(|e| e.into_error())?;
+        file.seek(SeekFrom::Start(0))?;
+        let schema = Arc::new(schema_from_columns(columns));
+        let mut output = match format {
+            OutputFormat::Parquet => {
+                let props = WriterProperties::builder()
+                    .set_max_row_group_size(row_group_size)
+                    .set_compression(Compression::SNAPPY)
+                    .build();
+                ColumnarOutput::Parquet(ArrowWriter::try_new(writer, schema.clone(), Some(props))?)
+            }
+            _ => ColumnarOutput::Arrow(FileWriter::try_new(writer, &schema)?),
+        };
+
+        let mut rows = Vec::with_capacity(row_group_size);
+        for line in BufReader::new(file).lines() {
+            rows.push(serde_json::from_str::<Record>(&line?)?);
+            if rows.len() >= row_group_size {
+                output.write(&build_batch(&schema, &rows)?)?;
+                rows.clear();
+            }
+        }
+        if !rows.is_empty() {
+            output.write(&build_batch(&schema, &rows)?)?;
+        }
+        let mut writer = output.into_inner()?;
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write + Send> ColumnarOutput<W> {
+    fn write(&mut self, batch: &RecordBatch) -> Result<()> {
+        match self {
+            ColumnarOutput::Parquet(writer) => {
+                writer.write(batch)?;
+                // 每批记录单独作为一个 row group
+                writer.flush()?;
+            }
+            ColumnarOutput::Arrow(writer) => writer.write(batch)?,
+        }
+        Ok(())
+    }
+
+    fn into_inner(self) -> Result<W> {
+        let writer = match self {
+            // into_inner 会写出 parquet 的 footer
+            ColumnarOutput::Parquet(writer) => writer.into_inner()?,
+            ColumnarOutput::Arrow(writer) => writer.into_inner()?,
+        };
+        Ok(writer)
+    }
+}
+
+/// 整数和浮点数混合时为浮点数, 其他类型冲突或嵌套的值为字符串, 全部为 null 的列也是字符串
+pub fn infer_schema(rows: &[Record]) -> Schema {
+    let mut columns = Vec::new();
+    for row in rows {
+        merge_column_types(&mut columns, row);
+    }
+    schema_from_columns(columns)
+}
+
+fn merge_column_types(columns: &mut Vec<(String, Option<DataType>)>, row: &Record) {
+    for (name, value) in row {
+        let ty = match value {
+            Value::Null => None,
+            Value::Bool(_) => Some(DataType::Boolean),
+            Value::Number(n) if n.is_i64() => Some(DataType::Int64),
+            Value::Number(_) => Some(DataType::Float64),
+            _ => Some(DataType::Utf8),
+        };
+        let index = match columns.iter().position(|(n, _)| n == name) {
+            Some(index) => index,
+            None => {
+                columns.push((name.clone(), None));
+                columns.len() - 1
+            }
+        };
+        let current = &mut columns[index].1;
+        *current = match (current.take(), ty) {
+            (None, ty) | (ty, None) => ty,
+            (Some(a), Some(b)) if a == b => Some(a),
+            (Some(DataType::Int64), Some(DataType::Float64))
+            | (Some(DataType::Float64), Some(DataType::Int64)) => Some(DataType::Float64),
+            _ => Some(DataType::Utf8),
+        };
+    }
+}
+
+fn schema_from_columns(columns: Vec<(String, Option<DataType>)>) -> Schema {
+    let fields = columns
+        .into_iter()
+        .map(|(name, ty)| Field::new(name, ty.unwrap_or(DataType::Utf8), true))
+        .collect::<Vec<_>>();
+    Schema::new(fields)
+}
+
+fn build_batch(schema: &SchemaRef, rows: &[Record]) -> Result<RecordBatch> {
+    let columns = schema
+        .fields()
+        .iter()
+        .map(|field| build_column(field, rows))
+        .collect::<Result<Vec<_>>>()?;
+    Ok(RecordBatch::try_new(schema.clone(), columns)?)
+}
+
+fn build_column(field: &Field, rows: &[Record]) -> Result<ArrayRef> {
+    let values = rows.iter().map(|row| match row.get(field.name()) {
+        None | Some(Value::Null) => None,
+        Some(value) => Some(value),
+    });
+    // 列类型根据所有记录推断, 不会出现不兼容的值
+    let mismatch = |value: &Value| {
+        anyhow::anyhow!(
+            "column {:?}: {} is not a valid {}",
+            field.name(),
+            value,
+            field.data_type()
+        )
+    };
+    let array: ArrayRef = match field.data_type() {
+        DataType::Int64 => {
+            let mut builder = Int64Builder::with_capacity(rows.len());
+            for value in values {
+                match value {
+                    Some(v) => builder.append_value(v.as_i64().ok_or_else(|| mismatch(v))?),
+                    None => builder.append_null(),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Float64 => {
+            let mut builder = Float64Builder::with_capacity(rows.len());
+            for value in values {
+                match value {
+                    Some(v) => builder.append_value(v.as_f64().ok_or_else(|| mismatch(v))?),
+                    None => builder.append_null(),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        DataType::Boolean => {
+            let mut builder = BooleanBuilder::with_capacity(rows.len());
+            for value in values {
+                match value {
+                    Some(v) => builder.append_value(v.as_bool().ok_or_else(|| mismatch(v))?),
+                    None => builder.append_null(),
+                }
+            }
+            Arc::new(builder.finish())
+        }
+        _ => {
+            let mut builder = StringBuilder::new();
+            for value in values {
+                builder.append_option(value.and_then(cell_to_string));
+            }
+            Arc::new(builder.finish())
+        }
+    };
+    Ok(array)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{build_csv_reader, read_csv_values, CsvReaderOpts};
+    use arrow::ipc::reader::FileReader;
+    use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+    use std::io::Cursor;
+
+    fn write_juventus(format: OutputFormat, writer: impl Write + Send) -> Result<()> {
+        let opts = CsvReaderOpts::default();
+        let mut reader = build_csv_reader(std::fs::File::open("assets/juventus.csv")?, &opts)?;
+        let values = read_csv_values(&mut reader, &opts, None)?;
+        let mut writer = Box::new(ColumnarWriter::try_new(writer, format, 10)?);
+        for value in &values {
+            writer.write_record(value.as_object().unwrap())?;
+        }
+        writer.finish()
+    }
+
+    #[test]
+    fn test_write_parquet() -> Result<()> {
+        let file = tempfile::tempfile()?;
+        write_juventus(OutputFormat::Parquet, file.try_clone()?)?;
+        let builder = ParquetRecordBatchReaderBuilder::try_new(file)?;
+        assert_eq!(builder.metadata().num_row_groups(), 3);
+        let schema = builder.schema().clone();
+        assert_eq!(schema.field(0).data_type(), &DataType::Utf8);
+        assert_eq!(
+            schema.field_with_name("Kit Number")?.data_type(),
+            &DataType::Int64
+        );
+        let rows = builder
+            .build()?
+            .map(|batch| Ok(batch?.num_rows()))
+            .sum::<Result<usize>>()?;
+        assert_eq!(rows, 27);
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_arrow_ipc() -> Result<()> {
+        let mut buf = Vec::new();
+        write_juventus(OutputFormat::Arrow, &mut buf)?;
+        let reader = FileReader::try_new(Cursor::new(buf), None)?;
+        assert_eq!(reader.num_batches(), 3);
+        let batches = reader.collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(batches.iter().map(|b| b.num_rows()).sum::<usize>(), 27);
+
+        let rows = serde_json::from_value::<Vec<Record>>(serde_json::json!([
+            {"a": 1, "b": null, "c": true},
+            {"a": 2.5, "b": null, "c": "yes"},
+        ]))?;
+        let schema = infer_schema(&rows);
+        let types = schema.fields().iter().map(|f| f.data_type().clone());
+        assert_eq!(
+            types.collect::<Vec<_>>(),
+            vec![DataType::Float64, DataType::Utf8, DataType::Utf8]
+        );
+        assert!(ColumnarWriter::try_new(Vec::new(), OutputFormat::Json, 10).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_columnar_types_from_all_rows() -> Result<()> {
+        // 第二个 row group 中 a 变为浮点数, b 出现字符串, c 是新出现的列
+        let rows = serde_json::from_value::<Vec<Record>>(serde_json::json!([
+            {"a": 1, "b": 1},
+            {"a": 2, "b": 2},
+            {"a": 2.5, "b": "n/a", "c": true},
+        ]))?;
+        let mut buf = Vec::new();
+        let mut writer = Box::new(ColumnarWriter::try_new(&mut buf, OutputFormat::Arrow, 2)?);
+        for row in &rows {
+            writer.write_record(row)?;
+        }
+        writer.finish()?;
+        let reader = FileReader::try_new(Cursor::new(buf), None)?;
+        let schema = reader.schema();
+        let types = schema.fields().iter().map(|f| f.data_type().clone());
+        assert_eq!(
+            types.collect::<Vec<_>>(),
+            vec![DataType::Float64, DataType::Utf8, DataType::Boolean]
+        );
+        let batches = reader.collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(batches.len(), 2);
+        assert_eq!(batches[1].num_rows(), 1);
+        Ok(())
+    }
+}
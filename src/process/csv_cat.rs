@@ -0,0 +1,107 @@
+use std::io::Write;
+
+use anyhow::Result;
+use csv::{StringRecord, Writer};
+
+use crate::{
+    cli::{CsvCatOpts, CsvReaderOpts},
+    get_reader, write_output,
+};
+
+use super::csv_convert::{build_csv_reader, build_csv_writer, read_csv_headers};
+
+pub fn process_csv_cat(opts: &CsvCatOpts) -> Result<()> {
+    write_output(&opts.output, opts.force, |output| {
+        cat_csv(
+            &opts.inputs,
+            build_csv_writer(output, &opts.reader),
+            &opts.reader,
+        )
+    })
+}
+
+/**
+ * 依次输出多个文件的所有行, 表头不同时输出所有列的并集 (按第一次出现的顺序),
+ * 文件中缺少的列留空; 没有表头时直接拼接
+ */
+pub fn cat_csv<W: Write>(
+    inputs: &[String],
+    mut writer: Writer<W>,
+    opts: &CsvReaderOpts,
+) -> Result<()> {
+    // 先读出所有文件的表头确定输出的列, 再逐个文件流式输出 (stdin 只能读一次)
+    let mut readers = Vec::with_capacity(inputs.len());
+    let mut headers = StringRecord::new();
+    for input in inputs {
+        let mut reader = build_csv_reader(get_reader(input)?, opts)?;
+        let file_headers = read_csv_headers(&mut reader, opts.header)?;
+        for h in file_headers.iter() {
+            if !headers.iter().any(|existing| existing == h) {
+                headers.push_field(h);
+            }
+        }
+        readers.push((reader, file_headers));
+    }
+    if opts.header {
+        writer.write_record(&headers)?;
+    }
+
+    for (mut reader, file_headers) in readers {
+        let positions = headers
+            .iter()
+            .map(|h| file_headers.iter().position(|fh| fh == h))
+            .collect::<Vec<_>>();
+        for record in reader.records() {
+            let record = record?;
+            if !opts.header {
+                writer.write_record(&record)?;
+                continue;
+            }
+            let row = positions
+                .iter()
+                .map(|pos| pos.and_then(|i| record.get(i)).unwrap_or_default());
+            writer.write_record(row)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cat_csv() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let a = dir.path().join("a.csv");
+        let b = dir.path().join("b.csv");
+        std::fs::write(&a, "name;kit\nPerin;37\n")?;
+        std::fs::write(&b, "name;team\nDybala;Juventus\n")?;
+        let inputs = [a, b].map(|p| p.to_string_lossy().to_string());
+
+        let opts = CsvReaderOpts {
+            delimiter: b';',
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        cat_csv(&inputs, build_csv_writer(&mut buf, &opts), &opts)?;
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "name;kit;team\nPerin;37;\nDybala;;Juventus\n"
+        );
+
+        let opts = CsvReaderOpts {
+            delimiter: b';',
+            header: false,
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        cat_csv(&inputs, build_csv_writer(&mut buf, &opts), &opts)?;
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "name;kit\nPerin;37\nname;team\nDybala;Juventus\n"
+        );
+        Ok(())
+    }
+}
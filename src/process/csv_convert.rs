@@ -1,39 +1,892 @@
-use std::fs;
-
-use anyhow::{Ok, Result};
-use csv::Reader;
-use serde::{Deserialize, Serialize};
-
-use crate::cli::OutputFormat;
-
-#[derive(Debug, Deserialize, Serialize)]
-#[serde(rename_all = "PascalCase")]
-pub struct Player {
-    name: String,
-    position: String,
-    #[serde(rename = "DOB")]
-    dob: String,
-    nationality: String,
-    #[serde(rename = "Kit Number")]
-    kit: u8,
-}
-
-pub fn process_csv(input: &str, output: String, format: OutputFormat) -> Result<()> {
-    let mut reader = Reader::from_path(input)?;
-    let mut ret = Vec::with_capacity(128);
-    let headers = reader.headers()?.clone();
-    for result in reader.records() {
-        let record = result?;
-        let json_value = headers
+use std::{
+    cell::RefCell,
+    collections::HashSet,
+    io::{self, Read, Seek, SeekFrom, Write},
+    rc::Rc,
+};
+
+use anyhow::{Context, Result};
+use csv::{Reader, ReaderBuilder, StringRecord, Writer, WriterBuilder};
+use serde_json::Value;
+
+use crate::{
+    cli::{CsvOpts, CsvReaderOpts, CsvTransformOpts, OnError, OutputFormat},
+    get_content, get_reader, write_output,
+};
+
+use super::{
+    csv_arrow::ColumnarWriter,
+    csv_encoding::decode_reader,
+    csv_errors::BadRows,
+    csv_expr::Expr,
+    csv_schema::{infer_column_types, ColumnType, CsvSchema},
+    csv_writer::{
+        cell_to_string, new_record_writer, GroupedWriter, Record, RecordWriter, TomlDocument,
+    },
+    csv_xlsx::{convert_xlsx, is_workbook, read_xlsx},
+};
+
+/// 返回按 --on-error 跳过的行数
+pub fn process_csv(opts: &CsvOpts) -> Result<usize> {
+    let output = match &opts.output {
+        Some(output) => output.clone(),
+        None => format!("output.{}", opts.format),
+    };
+    let schema = opts.schema.as_deref().map(CsvSchema::load).transpose()?;
+    let mut skipped = 0;
+    write_output(&output, opts.force, |output| match opts.on_error {
+        OnError::Collect => {
+            // 错误行先写到匿名临时文件, 有错误行时才生成报告, 已存在时同样需要 --force 才会覆盖
+            let mut spool = tempfile::tempfile()?;
+            let bad_rows = BadRows::new(opts.on_error, Some(Box::new(spool.try_clone()?)))?;
+            skipped = convert_to(opts, schema.as_ref(), output, bad_rows)?;
+            if skipped == 0 {
+                return Ok(());
+            }
+            spool.seek(SeekFrom::Start(0))?;
+            write_output(&opts.error_report, opts.force, |mut report| {
+                io::copy(&mut spool, &mut report)?;
+                report.flush()?;
+                Ok(())
+            })
+        }
+        _ => {
+            let bad_rows = BadRows::new(opts.on_error, None)?;
+            skipped = convert_to(opts, schema.as_ref(), output, bad_rows)?;
+            Ok(())
+        }
+    })?;
+    Ok(skipped)
+}
+
+/// 返回跳过的行数
+fn convert_to(
+    opts: &CsvOpts,
+    schema: Option<&CsvSchema>,
+    output: Box<dyn Write + Send>,
+    mut bad_rows: BadRows,
+) -> Result<usize> {
+    let writer: Box<dyn RecordWriter> =
+        match (&opts.group_by, opts.format) {
+            (Some(column), format) => Box::new(GroupedWriter::try_new(output, format, column)?),
+            (None, OutputFormat::Parquet | OutputFormat::Arrow) => Box::new(
+                ColumnarWriter::try_new(output, opts.format, opts.row_group_size)?,
+            ),
+            (None, format) => new_record_writer(output, format)?,
+        };
+    // stdin 无法根据扩展名判断, 需要通过 --sheet 指定为工作簿
+    if is_workbook(&opts.input) || opts.sheet.is_some() {
+        convert_xlsx(
+            get_content(&opts.input)?,
+            opts.sheet.as_deref(),
+            opts.reader.header,
+            schema,
+            &opts.transform,
+            writer,
+            &mut bad_rows,
+        )?;
+    } else {
+        convert_csv(
+            get_reader(&opts.input)?,
+            &opts.reader,
+            schema,
+            opts.infer_rows,
+            &opts.transform,
+            writer,
+            &mut bad_rows,
+        )?;
+    }
+    bad_rows.finish()
+}
+
+/**
+ * 逐行读取并写出, 只缓存前 infer_rows 行用来推断列类型;
+ * 无法解析 (例如 I/O 以外的 csv 错误), 列数与表头不同 (没有 --flexible 时) 或类型转换失败的行
+ * 交给 bad_rows 处理, 报告中的原始文本取自输入 (转码为 UTF-8 之后)
+ */
+pub fn convert_csv<'a, R: Read + 'a>(
+    input: R,
+    opts: &CsvReaderOpts,
+    schema: Option<&CsvSchema>,
+    infer_rows: usize,
+    transform: &CsvTransformOpts,
+    mut writer: Box<dyn RecordWriter + '_>,
+    bad_rows: &mut BadRows,
+) -> Result<()> {
+    let raw = RawInput::default();
+    // 列数不对的行也需要读出来, 才能按 --on-error 处理并记录原始文本
+    let reader_opts = CsvReaderOpts {
+        flexible: true,
+        ..opts.clone()
+    };
+    let input = raw.record(decode_reader(input, opts.encoding)?);
+    let mut reader = csv_reader_builder(&reader_opts).from_reader(input);
+    let headers = read_csv_headers(&mut reader, opts.header)?;
+    let transform = RecordTransform::try_new(transform, &headers)?;
+    let mut sample = Vec::new();
+    let mut ends = Vec::new();
+    while sample.len() < infer_rows {
+        let Some((record, end)) = read_record(&mut reader, &raw, bad_rows)? else {
+            break;
+        };
+        sample.push(record);
+        ends.push(end);
+    }
+    let converter = RecordConverter::new(headers, &sample, schema);
+    let width = converter.headers().len();
+    let mut sample = sample.into_iter().zip(ends);
+    loop {
+        let next = match sample.next() {
+            Some(next) => Some(next),
+            None => read_record(&mut reader, &raw, bad_rows)?,
+        };
+        let Some((record, end)) = next else {
+            break;
+        };
+        let start = record.position().map_or(0, |pos| pos.byte());
+        let line = record.position().map_or(0, |pos| pos.line());
+        let ret = match record.len() {
+            len if len != width && !opts.flexible => Err(anyhow::anyhow!(
+                "line {}: expected {} fields, found {}",
+                line,
+                width,
+                len
+            )),
+            _ => converter.convert(&record),
+        };
+        let record = match ret {
+            Ok(record) => record,
+            Err(e) => {
+                bad_rows.handle(line, &raw.text(start, end), e)?;
+                raw.discard_before(end);
+                continue;
+            }
+        };
+        raw.discard_before(end);
+        if let Some(record) = transform.apply(record) {
+            writer.write_record(&record)?;
+        }
+    }
+    writer.finish()
+}
+
+/// 读取下一行, 返回记录和它在输入中的结束位置; 解析失败的行交给 bad_rows 处理后继续读取
+fn read_record<R: Read>(
+    reader: &mut Reader<R>,
+    raw: &RawInput,
+    bad_rows: &mut BadRows,
+) -> Result<Option<(StringRecord, u64)>> {
+    loop {
+        let start = reader.position().clone();
+        let mut record = StringRecord::new();
+        match reader.read_record(&mut record) {
+            Ok(true) => return Ok(Some((record, reader.position().byte()))),
+            Ok(false) => return Ok(None),
+            // 读取失败不是某一行的问题
+            Err(e) if e.is_io_error() => return Err(e.into()),
+            Err(e) => {
+                let pos = e.position().unwrap_or(&start);
+                let (line, start) = (pos.line(), pos.byte());
+                let end = reader.position().byte();
+                let err = anyhow::Error::new(e).context(format!("line {}", line));
+                bad_rows.handle(line, &raw.text(start, end), err)?;
+            }
+        }
+    }
+}
+
+/// 记录 csv Reader 读过的字节, 用于在错误报告中输出出错行的原始文本
+#[derive(Clone, Default)]
+struct RawInput(Rc<RefCell<RawBuffer>>);
+
+#[derive(Default)]
+struct RawBuffer {
+    // bytes[0] 在输入中的位置
+    offset: u64,
+    bytes: Vec<u8>,
+}
+
+struct RecordingReader<R> {
+    inner: R,
+    raw: RawInput,
+}
+
+impl RawInput {
+    fn record<R: Read>(&self, inner: R) -> RecordingReader<R> {
+        RecordingReader {
+            inner,
+            raw: self.clone(),
+        }
+    }
+
+    /// [start, end) 之间的原始文本, 不包含结尾的换行
+    fn text(&self, start: u64, end: u64) -> String {
+        let buf = self.0.borrow();
+        let from = (start.saturating_sub(buf.offset) as usize).min(buf.bytes.len());
+        let to = (end.saturating_sub(buf.offset) as usize).clamp(from, buf.bytes.len());
+        let text = String::from_utf8_lossy(&buf.bytes[from..to]);
+        text.trim_end_matches(['\r', '\n']).to_string()
+    }
+
+    /// offset 之前的字节不会再用到
+    fn discard_before(&self, offset: u64) {
+        let mut buf = self.0.borrow_mut();
+        let n = (offset.saturating_sub(buf.offset) as usize).min(buf.bytes.len());
+        buf.bytes.drain(..n);
+        buf.offset += n as u64;
+    }
+}
+
+impl<R: Read> Read for RecordingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let n = self.inner.read(buf)?;
+        self.raw.0.borrow_mut().bytes.extend_from_slice(&buf[..n]);
+        Ok(n)
+    }
+}
+
+/// 在序列化之前对每条记录做过滤 (--where), 选择列 (--select), 重命名 (--rename) 和嵌套 (--nest)
+pub struct RecordTransform {
+    filter: Option<Expr>,
+    // (原列名, 输出列名), 为 None 时原样输出
+    columns: Option<Vec<(String, String)>>,
+    nest: bool,
+}
+
+impl RecordTransform {
+    pub fn try_new(opts: &CsvTransformOpts, headers: &StringRecord) -> Result<Self> {
+        let names = headers.iter().collect::<Vec<_>>();
+        let check = |col: &str| {
+            if names.contains(&col) {
+                Ok(())
+            } else {
+                Err(anyhow::anyhow!("column {:?} not found", col))
+            }
+        };
+        if let Some(filter) = &opts.filter {
+            filter.columns().into_iter().try_for_each(check)?;
+        }
+        opts.select.iter().try_for_each(|col| check(col))?;
+        opts.rename.iter().try_for_each(|(col, _)| check(col))?;
+
+        let columns = if opts.select.is_empty() && opts.rename.is_empty() {
+            None
+        } else {
+            let selected = if opts.select.is_empty() {
+                names.iter().map(|s| s.to_string()).collect()
+            } else {
+                opts.select.clone()
+            };
+            let columns = selected
+                .into_iter()
+                .map(|col| {
+                    let name = opts
+                        .rename
+                        .iter()
+                        .find(|(from, _)| *from == col)
+                        .map_or_else(|| col.clone(), |(_, to)| to.clone());
+                    (col, name)
+                })
+                .collect();
+            Some(columns)
+        };
+        Ok(Self {
+            filter: opts.filter.clone(),
+            columns,
+            nest: opts.nest,
+        })
+    }
+
+    /// 不满足过滤条件时返回 None
+    pub fn apply(&self, mut record: Record) -> Option<Record> {
+        if let Some(filter) = &self.filter {
+            if !filter.is_match(&record) {
+                return None;
+            }
+        }
+        if let Some(columns) = &self.columns {
+            record = columns
+                .iter()
+                .map(|(from, to)| {
+                    let value = record.get_mut(from).map(Value::take).unwrap_or_default();
+                    (to.clone(), value)
+                })
+                .collect();
+        }
+        match self.nest {
+            true => Some(nest_record(record)),
+            false => Some(record),
+        }
+    }
+}
+
+/**
+ * 按 "." 拆分列名并构建嵌套对象, 例如 address.city 和 address.zip 合并为 address: { city, zip };
+ * 路径上已经有同名的普通值时 (同时存在 address 和 address.city 两列) 保留原列名
+ */
+pub fn nest_record(record: Record) -> Record {
+    let mut ret = Record::new();
+    for (key, value) in record {
+        let path = key.split('.').collect::<Vec<_>>();
+        if path.iter().any(|part| part.is_empty()) {
+            ret.insert(key, value);
+            continue;
+        }
+        if let Err(value) = insert_path(&mut ret, &path, value) {
+            ret.insert(key, value);
+        }
+    }
+    ret
+}
+
+// 路径冲突时把值原样返回
+fn insert_path(node: &mut Record, path: &[&str], value: Value) -> Result<(), Value> {
+    let Some((first, rest)) = path.split_first() else {
+        return Err(value);
+    };
+    if rest.is_empty() {
+        if node.get(*first).is_some_and(Value::is_object) {
+            return Err(value);
+        }
+        node.insert(first.to_string(), value);
+        return Ok(());
+    }
+    match node
+        .entry(*first)
+        .or_insert_with(|| Value::Object(Record::new()))
+    {
+        Value::Object(child) => insert_path(child, rest, value),
+        _ => Err(value),
+    }
+}
+
+/// 将 csv 的一行按列类型转换为 json 对象
+pub struct RecordConverter {
+    headers: StringRecord,
+    types: Vec<ColumnType>,
+    // schema 中声明了类型的列, 转换失败时直接报错
+    declared: Vec<bool>,
+}
+
+impl RecordConverter {
+    pub fn new(headers: StringRecord, sample: &[StringRecord], schema: Option<&CsvSchema>) -> Self {
+        let types = infer_column_types(&headers, sample, schema);
+        let declared = headers
             .iter()
-            .zip(record.iter())
-            .collect::<serde_json::Value>(); // collect 会将元组转换为指定的类型
-        ret.push(json_value);
+            .map(|name| schema.and_then(|schema| schema.column_type(name)).is_some())
+            .collect();
+        Self {
+            headers,
+            types,
+            declared,
+        }
     }
-    let content = match format {
-        OutputFormat::Json => serde_json::to_string_pretty(&ret)?,
-        OutputFormat::Yaml => serde_yaml::to_string(&ret)?,
+
+    pub fn headers(&self) -> &StringRecord {
+        &self.headers
+    }
+
+    pub fn convert(&self, record: &StringRecord) -> Result<Record> {
+        let line = record.position().map_or(0, |pos| pos.line());
+        // --flexible 时缺少的列为 null, 多出的列被忽略
+        self.headers
+            .iter()
+            .zip(self.types.iter().zip(&self.declared))
+            .enumerate()
+            .map(|(i, (name, (ty, declared)))| {
+                let Some(value) = record.get(i) else {
+                    return Ok((name.to_string(), Value::Null));
+                };
+                let value = match ty.convert(value) {
+                    Ok(value) => value,
+                    // 推断出的类型只基于部分数据, 转换失败时保留原始字符串
+                    Err(_) if !declared => Value::from(value),
+                    Err(e) => {
+                        return Err(e).with_context(|| {
+                            format!("line {}: column {:?} is not a valid {}", line, name, ty)
+                        })
+                    }
+                };
+                Ok((name.to_string(), value))
+            })
+            .collect()
+    }
+}
+
+/// 将 json/yaml 中的对象数组转换回 csv, 嵌套对象展开为 a.b 形式的列
+pub fn process_csv_from(
+    input: &str,
+    output: &str,
+    format: OutputFormat,
+    delimiter: u8,
+    force: bool,
+) -> Result<()> {
+    let content = get_content(input)?;
+    let values = parse_values(&content, format)?;
+    write_output(output, force, |writer| {
+        values_to_csv(&values, writer, delimiter)
+    })
+}
+
+fn parse_values(content: &[u8], format: OutputFormat) -> Result<Vec<Value>> {
+    let value = match format {
+        OutputFormat::Json => serde_json::from_slice(content)?,
+        OutputFormat::Yaml => serde_yaml::from_slice(content)?,
+        OutputFormat::Ndjson => {
+            return serde_json::Deserializer::from_slice(content)
+                .into_iter()
+                .collect::<Result<_, _>>()
+                .map_err(Into::into)
+        }
+        OutputFormat::Toml => {
+            let doc: TomlDocument = toml::from_slice(content)?;
+            return Ok(doc.rows.into_iter().map(Value::Object).collect());
+        }
+        OutputFormat::Xlsx => {
+            let (_, records) = read_xlsx(content.to_vec(), None, true)?;
+            return Ok(records.into_iter().map(Value::Object).collect());
+        }
+        _ => anyhow::bail!("unsupported input format: {}", format),
     };
-    fs::write(output, content)?;
+    match value {
+        Value::Array(values) => Ok(values),
+        // 单个对象当作只有一行
+        value @ Value::Object(_) => Ok(vec![value]),
+        _ => anyhow::bail!("input must be an array of objects"),
+    }
+}
+
+/// 所有记录的列取并集, 按第一次出现的顺序排列
+pub fn values_to_csv(values: &[Value], writer: impl Write, delimiter: u8) -> Result<()> {
+    let mut rows = Vec::with_capacity(values.len());
+    let mut headers = Vec::new();
+    let mut seen = HashSet::new();
+    for value in values {
+        let mut row = Record::new();
+        flatten_value(None, value, &mut row);
+        for key in row.keys() {
+            if seen.insert(key.clone()) {
+                headers.push(key.clone());
+            }
+        }
+        rows.push(row);
+    }
+
+    let mut writer = WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(writer);
+    writer.write_record(&headers)?;
+    for row in rows {
+        let record = headers
+            .iter()
+            .map(|h| row.get(h).and_then(cell_to_string).unwrap_or_default());
+        writer.write_record(record)?;
+    }
+    writer.flush()?;
     Ok(())
 }
+
+// 数组没有合适的列名, 保留为 json 字符串
+fn flatten_value(prefix: Option<&str>, value: &Value, row: &mut Record) {
+    match value {
+        Value::Object(map) => {
+            for (k, v) in map {
+                let key = match prefix {
+                    Some(prefix) => format!("{}.{}", prefix, k),
+                    None => k.clone(),
+                };
+                flatten_value(Some(&key), v, row);
+            }
+        }
+        Value::Array(_) => {
+            row.insert(
+                prefix.unwrap_or("value").to_string(),
+                Value::from(value.to_string()),
+            );
+        }
+        v => {
+            row.insert(prefix.unwrap_or("value").to_string(), v.clone());
+        }
+    }
+}
+
+/// 根据命令行选项构建 csv Reader, 其他 csv 子命令也通过它读取文件; 输入先被转码为 UTF-8
+pub fn build_csv_reader<'a, R: Read + 'a>(
+    reader: R,
+    opts: &CsvReaderOpts,
+) -> Result<Reader<Box<dyn Read + 'a>>> {
+    let reader = decode_reader(reader, opts.encoding)?;
+    Ok(csv_reader_builder(opts).from_reader(reader))
+}
+
+fn csv_reader_builder(opts: &CsvReaderOpts) -> ReaderBuilder {
+    let mut builder = ReaderBuilder::new();
+    builder
+        .delimiter(opts.delimiter)
+        .quote(opts.quote)
+        .escape(opts.escape)
+        // 指定了转义字符时不再使用 "" 转义
+        .double_quote(opts.escape.is_none())
+        .comment(opts.comment)
+        .has_headers(opts.header)
+        .flexible(opts.flexible);
+    builder
+}
+
+/// 输出 csv 时使用与输入相同的分隔符, 引号和转义字符
+pub fn build_csv_writer<W: Write>(writer: W, opts: &CsvReaderOpts) -> Writer<W> {
+    WriterBuilder::new()
+        .delimiter(opts.delimiter)
+        .quote(opts.quote)
+        .escape(opts.escape.unwrap_or(b'\\'))
+        .double_quote(opts.escape.is_none())
+        .flexible(opts.flexible)
+        .from_writer(writer)
+}
+
+/// 按输出选项把一行编码为 csv 文本, 包含结尾的换行
+pub fn encode_csv_record(record: &StringRecord, opts: &CsvReaderOpts) -> Result<Vec<u8>> {
+    let mut line = Vec::new();
+    let mut writer = build_csv_writer(&mut line, opts);
+    writer.write_record(record)?;
+    writer.flush()?;
+    drop(writer);
+    Ok(line)
+}
+
+/// 没有表头时用第一行的列数生成 col1..colN
+pub fn read_csv_headers<R: Read>(reader: &mut Reader<R>, has_header: bool) -> Result<StringRecord> {
+    // has_headers(false) 时 headers() 返回第一行但不会消费它
+    let headers = reader.headers()?;
+    if has_header {
+        return Ok(headers.clone());
+    }
+    Ok((1..=headers.len()).map(|i| format!("col{}", i)).collect())
+}
+
+/// 将列名转换为下标, 未指定列时返回全部列
+pub fn column_indices(headers: &StringRecord, columns: &[String]) -> Result<Vec<usize>> {
+    if columns.is_empty() {
+        return Ok((0..headers.len()).collect());
+    }
+    columns
+        .iter()
+        .map(|col| {
+            headers
+                .iter()
+                .position(|h| h == col)
+                .with_context(|| format!("column {:?} not found", col))
+        })
+        .collect()
+}
+
+/// 将整个文件读入内存, 按全部数据推断列类型
+pub fn read_csv_values<R: Read>(
+    reader: &mut Reader<R>,
+    opts: &CsvReaderOpts,
+    schema: Option<&CsvSchema>,
+) -> Result<Vec<Value>> {
+    let headers = read_csv_headers(reader, opts.header)?;
+    let records = reader.records().collect::<Result<Vec<_>, _>>()?;
+    let converter = RecordConverter::new(headers, &records, schema);
+    records
+        .iter()
+        .map(|record| Ok(Value::Object(converter.convert(record)?)))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::get_reader;
+
+    fn read_fixture(input: &str, opts: &CsvReaderOpts) -> Result<Vec<Value>> {
+        let mut reader = build_csv_reader(get_reader(input)?, opts)?;
+        read_csv_values(&mut reader, opts, None)
+    }
+
+    #[test]
+    fn test_read_csv_with_delimiter() -> Result<()> {
+        let expected = read_fixture("assets/juventus.csv", &CsvReaderOpts::default())?;
+
+        let opts = CsvReaderOpts {
+            delimiter: b';',
+            ..Default::default()
+        };
+        let ret = read_fixture("fixtures/juventus_semicolon.csv", &opts)?;
+        assert_eq!(ret.len(), 5);
+        assert_eq!(ret, expected[..5]);
+
+        let opts = CsvReaderOpts {
+            delimiter: b'\t',
+            ..Default::default()
+        };
+        let ret = read_fixture("fixtures/juventus.tsv", &opts)?;
+        assert_eq!(ret, expected[..5]);
+        assert_eq!(ret[0]["DOB"], "Apr 18, 1990 (29)");
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_csv_with_quote_and_escape() -> Result<()> {
+        let opts = CsvReaderOpts {
+            quote: b'\'',
+            escape: Some(b'\\'),
+            ..Default::default()
+        };
+        let ret = read_fixture("fixtures/juventus_escaped.csv", &opts)?;
+        assert_eq!(ret.len(), 5);
+        assert_eq!(ret[1]["Name"], "Mattia 'The Wall' Perin");
+        assert_eq!(ret[1]["DOB"], "Nov 10, 1992 (26)");
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_csv_without_header() -> Result<()> {
+        let opts = CsvReaderOpts {
+            comment: Some(b'#'),
+            header: false,
+            ..Default::default()
+        };
+        let ret = read_fixture("fixtures/juventus_no_header.csv", &opts)?;
+        assert_eq!(ret.len(), 5);
+        assert_eq!(ret[0]["col1"], "Wojciech Szczesny");
+        assert_eq!(ret[0]["col5"], 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_csv_with_types() -> Result<()> {
+        let opts = CsvReaderOpts::default();
+        let ret = read_fixture("assets/juventus.csv", &opts)?;
+        assert_eq!(ret[0]["Kit Number"], 1);
+        assert_eq!(ret[0]["DOB"], "Apr 18, 1990 (29)");
+
+        let schema = CsvSchema::load("fixtures/juventus_schema.json")?;
+        let mut reader = build_csv_reader(get_reader("assets/juventus.csv")?, &opts)?;
+        let ret = read_csv_values(&mut reader, &opts, Some(&schema))?;
+        assert_eq!(ret[0]["Kit Number"], "1");
+        Ok(())
+    }
+
+    #[test]
+    fn test_csv_round_trip() -> Result<()> {
+        let values = read_fixture("assets/juventus.csv", &CsvReaderOpts::default())?;
+        let content = crate::format_values(&values, OutputFormat::Yaml)?;
+        let values = parse_values(content.as_bytes(), OutputFormat::Yaml)?;
+        let mut buf = Vec::new();
+        values_to_csv(&values, &mut buf, b',')?;
+        assert_eq!(
+            String::from_utf8(buf)?,
+            std::fs::read_to_string("assets/juventus.csv")?
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_values_to_csv_flatten() -> Result<()> {
+        let content = r#"
+            {"name": "a", "address": {"city": "Turin", "zip": "10121"}}
+            {"name": "b", "tags": [1, 2], "active": true}
+        "#;
+        let values = parse_values(content.as_bytes(), OutputFormat::Ndjson)?;
+        let mut buf = Vec::new();
+        values_to_csv(&values, &mut buf, b';')?;
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "name;address.city;address.zip;tags;active\na;Turin;10121;;\nb;;;[1,2];true\n"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_record_transform() -> Result<()> {
+        let opts = CsvReaderOpts::default();
+        let mut reader = build_csv_reader(get_reader("assets/juventus.csv")?, &opts)?;
+        let headers = read_csv_headers(&mut reader, true)?;
+        let values = read_csv_values(&mut reader, &opts, None)?;
+        let transform = CsvTransformOpts {
+            select: vec!["Name".to_string(), "Kit Number".to_string()],
+            rename: vec![("Kit Number".to_string(), "kit".to_string())],
+            filter: Some(r#"Position == "Goalkeeper" && Kit Number > 20"#.parse()?),
+            nest: false,
+        };
+        let transform = RecordTransform::try_new(&transform, &headers)?;
+        let ret = values
+            .into_iter()
+            .filter_map(|v| transform.apply(v.as_object()?.clone()))
+            .map(Value::Object)
+            .collect::<Vec<_>>();
+        assert_eq!(ret.len(), 3);
+        assert_eq!(
+            ret[0],
+            serde_json::json!({"Name": "Mattia Perin", "kit": 37})
+        );
+
+        let transform = CsvTransformOpts {
+            filter: Some("Salary > 10".parse()?),
+            ..Default::default()
+        };
+        assert!(RecordTransform::try_new(&transform, &headers).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_nest_record() -> Result<()> {
+        let record = serde_json::from_value(serde_json::json!({
+            "name": "Paulo Dybala",
+            "address.city": "Turin",
+            "address.geo.lat": 45.07,
+            "team": "Juventus",
+            "team.league": "Serie A",
+            ".hidden": 1,
+        }))?;
+        assert_eq!(
+            Value::Object(nest_record(record)),
+            serde_json::json!({
+                "name": "Paulo Dybala",
+                "address": { "city": "Turin", "geo": { "lat": 45.07 } },
+                "team": "Juventus",
+                "team.league": "Serie A",
+                ".hidden": 1,
+            })
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_convert_csv_streaming() -> Result<()> {
+        let opts = CsvReaderOpts::default();
+        let transform = CsvTransformOpts::default();
+        let input = "id,score\n1,10\n2,n/a\n3,\n";
+        let mut buf = Vec::new();
+        let writer = new_record_writer(&mut buf, OutputFormat::Ndjson)?;
+        // 只用第一行推断类型, 后续无法转换的值保留为字符串
+        convert_csv(
+            input.as_bytes(),
+            &opts,
+            None,
+            1,
+            &transform,
+            writer,
+            &mut BadRows::fail(),
+        )?;
+        let lines = String::from_utf8(buf)?;
+        let lines = lines.lines().collect::<Vec<_>>();
+        assert_eq!(lines[0], r#"{"id":1,"score":10}"#);
+        assert_eq!(lines[1], r#"{"id":2,"score":"n/a"}"#);
+        assert_eq!(lines[2], r#"{"id":3,"score":null}"#);
+
+        let schema: CsvSchema =
+            serde_json::from_str(r#"{ "columns": [{ "name": "score", "type": "integer" }] }"#)?;
+        let writer = new_record_writer(std::io::sink(), OutputFormat::Ndjson)?;
+        let ret = convert_csv(
+            input.as_bytes(),
+            &opts,
+            Some(&schema),
+            1,
+            &transform,
+            writer,
+            &mut BadRows::fail(),
+        );
+        assert!(ret.is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_convert_csv_bad_rows() -> Result<()> {
+        let transform = CsvTransformOpts::default();
+        let schema: CsvSchema =
+            serde_json::from_str(r#"{ "columns": [{ "name": "score", "type": "integer" }] }"#)?;
+        let input = "id,score\n1,10\n2,\"n/a\"\n3\n4,40,extra\n5,50\n";
+        let convert = |opts: &CsvReaderOpts, on_error| -> Result<(Vec<String>, Vec<u8>)> {
+            let (mut buf, mut report) = (Vec::new(), Vec::new());
+            let writer = new_record_writer(&mut buf, OutputFormat::Ndjson)?;
+            let mut bad_rows = BadRows::new(on_error, Some(Box::new(&mut report)))?;
+            convert_csv(
+                input.as_bytes(),
+                opts,
+                Some(&schema),
+                10,
+                &transform,
+                writer,
+                &mut bad_rows,
+            )?;
+            bad_rows.finish()?;
+            let lines = String::from_utf8(buf)?.lines().map(String::from).collect();
+            Ok((lines, report))
+        };
+
+        let opts = CsvReaderOpts::default();
+        let ret = convert(&opts, OnError::Fail);
+        assert!(ret.unwrap_err().to_string().contains("line 3"));
+
+        let (lines, report) = convert(&opts, OnError::Collect)?;
+        assert_eq!(lines, [r#"{"id":1,"score":10}"#, r#"{"id":5,"score":50}"#]);
+        let mut reader = csv::Reader::from_reader(report.as_slice());
+        let rows = reader.records().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(rows.len(), 3);
+        assert_eq!(&rows[0][0], "3");
+        // 原始文本取自输入, 保留引号
+        assert_eq!(&rows[0][2], "2,\"n/a\"");
+        assert_eq!(&rows[1][1], "line 4: expected 2 fields, found 1");
+        assert_eq!(&rows[2][2], "4,40,extra");
+
+        // --flexible 时缺少的列为 null, 多出的列被忽略
+        let opts = CsvReaderOpts {
+            flexible: true,
+            ..Default::default()
+        };
+        let (lines, _) = convert(&opts, OnError::Skip)?;
+        assert_eq!(lines[1], r#"{"id":3,"score":null}"#);
+        assert_eq!(lines[2], r#"{"id":4,"score":40}"#);
+
+        // 声明为 UTF-8 的输入中不合法的字节作为错误行处理
+        let opts = CsvReaderOpts {
+            encoding: "utf-8".parse()?,
+            ..Default::default()
+        };
+        let mut buf = Vec::new();
+        let writer = new_record_writer(&mut buf, OutputFormat::Ndjson)?;
+        let mut bad_rows = BadRows::new(OnError::Skip, None)?;
+        let input = b"id,score\n1,\xff\n2,20\n";
+        convert_csv(
+            &input[..],
+            &opts,
+            None,
+            10,
+            &transform,
+            writer,
+            &mut bad_rows,
+        )?;
+        assert_eq!(bad_rows.finish()?, 1);
+        assert_eq!(String::from_utf8(buf)?, "{\"id\":2,\"score\":20}\n");
+        Ok(())
+    }
+
+    #[test]
+    fn test_read_record_parse_error() -> Result<()> {
+        let raw = RawInput::default();
+        let input = raw.record(&b"a,b\n1,\xff\n2,3\n"[..]);
+        let mut reader = ReaderBuilder::new().from_reader(input);
+        reader.headers()?;
+        let mut report = Vec::new();
+        let mut bad_rows = BadRows::new(OnError::Collect, Some(Box::new(&mut report)))?;
+        let (record, end) = read_record(&mut reader, &raw, &mut bad_rows)?.unwrap();
+        assert_eq!(record, vec!["2", "3"]);
+        assert_eq!(raw.text(record.position().unwrap().byte(), end), "2,3");
+        assert!(read_record(&mut reader, &raw, &mut bad_rows)?.is_none());
+        assert_eq!(bad_rows.finish()?, 1);
+
+        let mut reader = csv::Reader::from_reader(report.as_slice());
+        let rows = reader.records().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(&rows[0][0], "2");
+        assert!(rows[0][1].starts_with("line 2: "));
+        assert_eq!(&rows[0][2], "1,\u{fffd}");
+
+        let mut reader = ReaderBuilder::new().from_reader(&b"a,b\n1,\xff\n"[..]);
+        reader.headers()?;
+        assert!(read_record(&mut reader, &raw, &mut BadRows::fail()).is_err());
+        Ok(())
+    }
+}
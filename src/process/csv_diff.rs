@@ -0,0 +1,262 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use colored::Colorize;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{
+    cli::{CsvDiffOpts, CsvReaderOpts},
+    get_reader,
+};
+
+use super::{
+    csv_convert::{build_csv_reader, read_csv_values},
+    csv_writer::{cell_to_string, Record},
+};
+
+/// 按 key 对比两个 csv 的结果, 行的顺序与原文件一致
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct CsvDiff {
+    pub columns_added: Vec<String>,
+    pub columns_removed: Vec<String>,
+    pub added: Vec<Record>,
+    pub removed: Vec<Record>,
+    pub modified: Vec<RowChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RowChange {
+    pub key: Record,
+    pub changes: Vec<CellChange>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CellChange {
+    pub column: String,
+    pub old: Value,
+    pub new: Value,
+}
+
+pub fn process_csv_diff(opts: &CsvDiffOpts) -> Result<CsvDiff> {
+    let old = read_table(&opts.old, &opts.reader)?;
+    let new = read_table(&opts.new, &opts.reader)?;
+    diff_records(&old, &new, &opts.keys)
+}
+
+fn read_table(input: &str, opts: &CsvReaderOpts) -> Result<Vec<Record>> {
+    let mut reader = build_csv_reader(get_reader(input)?, opts)?;
+    let values = read_csv_values(&mut reader, opts, None)?;
+    Ok(values
+        .into_iter()
+        .filter_map(|value| match value {
+            Value::Object(record) => Some(record),
+            _ => None,
+        })
+        .collect())
+}
+
+/**
+ * 两边都有的列逐个单元格比较, 只在一边存在的列作为表头变化单独列出;
+ * 比较时使用单元格的文本, 避免两个文件推断出的类型不同 (1 和 "1") 时误报
+ */
+pub fn diff_records(old: &[Record], new: &[Record], keys: &[String]) -> Result<CsvDiff> {
+    let old_columns = old.first().map(|r| r.keys().cloned().collect::<Vec<_>>());
+    let new_columns = new.first().map(|r| r.keys().cloned().collect::<Vec<_>>());
+    let (old_columns, new_columns) = (
+        old_columns.unwrap_or_default(),
+        new_columns.unwrap_or_default(),
+    );
+    for key in keys {
+        for (columns, rows) in [(&old_columns, old), (&new_columns, new)] {
+            if !rows.is_empty() && !columns.contains(key) {
+                anyhow::bail!("key column {:?} not found", key);
+            }
+        }
+    }
+
+    let key_of = |record: &Record| {
+        keys.iter()
+            .map(|k| record.get(k).and_then(cell_to_string).unwrap_or_default())
+            .collect::<Vec<_>>()
+    };
+    let index = |rows: &[Record]| -> Result<HashMap<Vec<String>, usize>> {
+        let mut index = HashMap::with_capacity(rows.len());
+        for (i, row) in rows.iter().enumerate() {
+            let key = key_of(row);
+            if index.insert(key.clone(), i).is_some() {
+                anyhow::bail!("duplicate key {:?}, use --key to pick unique columns", key);
+            }
+        }
+        Ok(index)
+    };
+    let old_index = index(old)?;
+    let new_index = index(new)?;
+
+    let mut diff = CsvDiff {
+        columns_added: new_columns
+            .iter()
+            .filter(|c| !old_columns.contains(c))
+            .cloned()
+            .collect(),
+        columns_removed: old_columns
+            .iter()
+            .filter(|c| !new_columns.contains(c))
+            .cloned()
+            .collect(),
+        ..Default::default()
+    };
+    for row in old {
+        let Some(&i) = new_index.get(&key_of(row)) else {
+            diff.removed.push(row.clone());
+            continue;
+        };
+        let other = &new[i];
+        let changes = old_columns
+            .iter()
+            .filter(|c| new_columns.contains(c))
+            .filter_map(|column| {
+                let (a, b) = (row.get(column), other.get(column));
+                let unchanged = a.and_then(cell_to_string) == b.and_then(cell_to_string);
+                (!unchanged).then(|| CellChange {
+                    column: column.clone(),
+                    old: a.cloned().unwrap_or(Value::Null),
+                    new: b.cloned().unwrap_or(Value::Null),
+                })
+            })
+            .collect::<Vec<_>>();
+        if !changes.is_empty() {
+            let key = keys
+                .iter()
+                .map(|k| (k.clone(), row.get(k).cloned().unwrap_or(Value::Null)))
+                .collect();
+            diff.modified.push(RowChange { key, changes });
+        }
+    }
+    diff.added = new
+        .iter()
+        .filter(|row| !old_index.contains_key(&key_of(row)))
+        .cloned()
+        .collect();
+    Ok(diff)
+}
+
+impl CsvDiff {
+    pub fn is_empty(&self) -> bool {
+        self.columns_added.is_empty()
+            && self.columns_removed.is_empty()
+            && self.added.is_empty()
+            && self.removed.is_empty()
+            && self.modified.is_empty()
+    }
+
+    /// 终端中显示的报告, 是否输出颜色通过 colored::control 控制
+    pub fn render(&self, keys: &[String]) -> String {
+        let describe = |record: &Record| {
+            record
+                .iter()
+                .map(|(k, v)| format!("{}={}", k, cell_to_string(v).unwrap_or_default()))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        let key_of = |record: &Record| {
+            let key = keys
+                .iter()
+                .map(|k| (k.clone(), record.get(k).cloned().unwrap_or(Value::Null)))
+                .collect();
+            describe(&key)
+        };
+
+        let mut lines = Vec::new();
+        for column in &self.columns_removed {
+            lines.push(format!("- column {}", column).red().to_string());
+        }
+        for column in &self.columns_added {
+            lines.push(format!("+ column {}", column).green().to_string());
+        }
+        for row in &self.removed {
+            lines.push(format!("- {}", key_of(row)).red().bold().to_string());
+            lines.push(format!("    {}", describe(row)).red().to_string());
+        }
+        for row in &self.added {
+            lines.push(format!("+ {}", key_of(row)).green().bold().to_string());
+            lines.push(format!("    {}", describe(row)).green().to_string());
+        }
+        for row in &self.modified {
+            lines.push(
+                format!("~ {}", describe(&row.key))
+                    .yellow()
+                    .bold()
+                    .to_string(),
+            );
+            for change in &row.changes {
+                let old = cell_to_string(&change.old).unwrap_or_default();
+                let new = cell_to_string(&change.new).unwrap_or_default();
+                lines.push(format!(
+                    "    {}: {} → {}",
+                    change.column,
+                    old.red(),
+                    new.green()
+                ));
+            }
+        }
+        lines.push(format!(
+            "{} added, {} removed, {} modified",
+            self.added.len(),
+            self.removed.len(),
+            self.modified.len()
+        ));
+        lines.join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn records(value: Value) -> Vec<Record> {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn test_diff_records() -> Result<()> {
+        let old = records(json!([
+            {"Name": "Mattia Perin", "Position": "Goalkeeper", "Kit Number": 37},
+            {"Name": "Emre Can", "Position": "Defensive Midfield", "Kit Number": 23},
+            {"Name": "Sami Khedira", "Position": "Central Midfield", "Kit Number": 6},
+        ]));
+        let new = records(json!([
+            {"Name": "Sami Khedira", "Position": "Central Midfield", "Kit Number": "6", "Age": 32},
+            {"Name": "Mattia Perin", "Position": "Goalkeeper", "Kit Number": 1, "Age": 26},
+            {"Name": "Merih Demiral", "Position": "Centre-Back", "Kit Number": 28, "Age": 21},
+        ]));
+        let keys = vec!["Name".to_string()];
+        let diff = diff_records(&old, &new, &keys)?;
+        assert_eq!(diff.columns_added, ["Age"]);
+        assert_eq!(diff.removed[0]["Name"], "Emre Can");
+        assert_eq!(diff.added[0]["Name"], "Merih Demiral");
+        // "6" 和 6 视为相同
+        assert_eq!(
+            diff.modified,
+            vec![RowChange {
+                key: records(json!([{"Name": "Mattia Perin"}])).remove(0),
+                changes: vec![CellChange {
+                    column: "Kit Number".to_string(),
+                    old: json!(37),
+                    new: json!(1),
+                }],
+            }]
+        );
+
+        colored::control::set_override(false);
+        let report = diff.render(&keys);
+        assert!(report.contains("~ Name=Mattia Perin\n    Kit Number: 37 → 1"));
+        assert!(report.ends_with("1 added, 1 removed, 1 modified"));
+
+        assert!(diff_records(&old, &new, &["Team".to_string()]).is_err());
+        assert!(diff_records(&old, &old, &[]).is_err());
+        assert!(diff_records(&old, &old, &keys)?.is_empty());
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use std::io::{Cursor, Read};
+
+use anyhow::Result;
+use chardetng::EncodingDetector;
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8};
+use encoding_rs_io::DecodeReaderBytesBuilder;
+
+use crate::cli::CsvEncoding;
+
+// 检测编码时最多读取的字节数
+const SAMPLE_SIZE: usize = 64 * 1024;
+
+/**
+ * 将输入转码为 UTF-8 并去掉 BOM, auto 时按以下顺序检测:
+ * BOM -> 没有 BOM 的 UTF-16 -> 合法的 UTF-8 -> chardetng 猜测 (GBK, Latin-1 等);
+ * UTF-8 的输入原样返回, 其中不合法的字节由 csv Reader 报错, 而不是被替换为 U+FFFD
+ */
+pub fn decode_reader<'a, R: Read + 'a>(
+    mut reader: R,
+    encoding: CsvEncoding,
+) -> Result<Box<dyn Read + 'a>> {
+    let mut sample = Vec::with_capacity(SAMPLE_SIZE);
+    (&mut reader)
+        .take(SAMPLE_SIZE as u64)
+        .read_to_end(&mut sample)?;
+    let eof = sample.len() < SAMPLE_SIZE;
+    let encoding = match encoding {
+        CsvEncoding::Auto => detect_encoding(&sample, eof),
+        CsvEncoding::Label(encoding) => encoding,
+    };
+    // 文件中的 BOM 优先于指定的编码
+    let (encoding, bom) = Encoding::for_bom(&sample).unwrap_or((encoding, 0));
+    if encoding == UTF_8 {
+        sample.drain(..bom);
+        return Ok(Box::new(Cursor::new(sample).chain(reader)));
+    }
+    let reader = Cursor::new(sample).chain(reader);
+    let reader = DecodeReaderBytesBuilder::new()
+        .encoding(Some(encoding))
+        .bom_override(true)
+        .strip_bom(true)
+        .build(reader);
+    Ok(Box::new(reader))
+}
+
+pub fn detect_encoding(sample: &[u8], eof: bool) -> &'static Encoding {
+    if let Some((encoding, _)) = Encoding::for_bom(sample) {
+        return encoding;
+    }
+    // 没有 BOM 的 UTF-16 中 ASCII 字符的另一个字节为 0, 需要在 UTF-8 之前检查 (0 也是合法的 UTF-8)
+    let zeros = |start: usize| {
+        sample
+            .iter()
+            .skip(start)
+            .step_by(2)
+            .filter(|&&b| b == 0)
+            .count()
+    };
+    let half = sample.len() / 2;
+    if half > 0 && zeros(1) * 2 > half {
+        return UTF_16LE;
+    }
+    if half > 0 && zeros(0) * 2 > half {
+        return UTF_16BE;
+    }
+    match std::str::from_utf8(sample) {
+        Ok(_) => return UTF_8,
+        // 采样可能在一个多字节字符中间截断
+        Err(e) if e.error_len().is_none() && !eof => return UTF_8,
+        Err(_) => {}
+    }
+    let mut detector = EncodingDetector::new();
+    detector.feed(sample, eof);
+    detector.guess(None, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use encoding_rs::{GBK, WINDOWS_1252};
+
+    fn decode(bytes: &[u8], encoding: CsvEncoding) -> Result<String> {
+        let mut ret = String::new();
+        decode_reader(bytes, encoding)?.read_to_string(&mut ret)?;
+        Ok(ret)
+    }
+
+    #[test]
+    fn test_decode_reader() -> Result<()> {
+        let text = "Name,Nationality\nWojciech Szczęsny,Poland\n";
+        assert_eq!(decode(text.as_bytes(), CsvEncoding::Auto)?, text);
+        let bom = [b"\xef\xbb\xbf".as_slice(), text.as_bytes()].concat();
+        assert_eq!(decode(&bom, CsvEncoding::Auto)?, text);
+
+        let utf16 = |bom: bool, le: bool| {
+            let mut bytes = Vec::new();
+            let units = bom.then_some(0xfeff).into_iter().chain(text.encode_utf16());
+            for unit in units {
+                match le {
+                    true => bytes.extend(unit.to_le_bytes()),
+                    false => bytes.extend(unit.to_be_bytes()),
+                }
+            }
+            bytes
+        };
+        assert_eq!(decode(&utf16(true, true), CsvEncoding::Auto)?, text);
+        assert_eq!(decode(&utf16(true, false), CsvEncoding::Auto)?, text);
+        assert_eq!(decode(&utf16(false, true), CsvEncoding::Auto)?, text);
+
+        let text = "姓名,位置,国籍\n布冯,门将,意大利\n基耶利尼,中后卫,意大利\n迪巴拉,前锋,阿根廷\n";
+        let (gbk, _, _) = GBK.encode(text);
+        assert_eq!(detect_encoding(&gbk, true), GBK);
+        assert_eq!(decode(&gbk, "gbk".parse()?)?, text);
+
+        let text = "Name,City\nJosé,São Paulo\nFrançois,Besançon\n";
+        let (latin1, _, _) = WINDOWS_1252.encode(text);
+        assert_eq!(decode(&latin1, CsvEncoding::Auto)?, text);
+        assert_eq!(decode(&latin1, "latin1".parse()?)?, text);
+        assert!("klingon".parse::<CsvEncoding>().is_err());
+
+        // 声明为 UTF-8 时不合法的字节原样保留
+        let mut ret = Vec::new();
+        decode_reader(&b"\xef\xbb\xbfa,\xff\n"[..], "utf-8".parse()?)?.read_to_end(&mut ret)?;
+        assert_eq!(ret, b"a,\xff\n");
+        Ok(())
+    }
+}
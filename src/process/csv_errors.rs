@@ -0,0 +1,91 @@
+use std::io::Write;
+
+use anyhow::Result;
+use csv::Writer;
+
+use crate::cli::OnError;
+
+/**
+ * 按 --on-error 处理无法转换的行: fail 直接返回错误, skip 跳过,
+ * collect 跳过并把 (line, reason, raw) 写到报告文件中
+ */
+pub struct BadRows<'a> {
+    on_error: OnError,
+    report: Option<Writer<Box<dyn Write + 'a>>>,
+    count: usize,
+}
+
+impl<'a> BadRows<'a> {
+    pub fn new(on_error: OnError, report: Option<Box<dyn Write + 'a>>) -> Result<Self> {
+        let report = match (on_error, report) {
+            (OnError::Collect, Some(report)) => {
+                let mut writer = Writer::from_writer(report);
+                writer.write_record(["line", "reason", "raw"])?;
+                Some(writer)
+            }
+            (OnError::Collect, None) => anyhow::bail!("--on-error collect requires a report file"),
+            _ => None,
+        };
+        Ok(Self {
+            on_error,
+            report,
+            count: 0,
+        })
+    }
+
+    /// 出错就中止, 与之前的行为一致
+    pub fn fail() -> Self {
+        Self {
+            on_error: OnError::Fail,
+            report: None,
+            count: 0,
+        }
+    }
+
+    /// raw 为这一行的原始文本, 只在 collect 时使用
+    pub fn handle(&mut self, line: u64, raw: &str, err: anyhow::Error) -> Result<()> {
+        if let OnError::Fail = self.on_error {
+            return Err(err);
+        }
+        self.count += 1;
+        if let Some(report) = &mut self.report {
+            report.write_record([&line.to_string(), &format!("{:#}", err), raw])?;
+        }
+        Ok(())
+    }
+
+    /// 返回跳过的行数
+    pub fn finish(self) -> Result<usize> {
+        if let Some(mut report) = self.report {
+            report.flush()?;
+        }
+        Ok(self.count)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bad_rows() -> Result<()> {
+        let mut rows = BadRows::fail();
+        assert!(rows.handle(2, "a,b", anyhow::anyhow!("bad")).is_err());
+
+        let mut rows = BadRows::new(OnError::Skip, None)?;
+        rows.handle(2, "a,b", anyhow::anyhow!("bad"))?;
+        assert_eq!(rows.finish()?, 1);
+
+        let mut buf = Vec::new();
+        let mut rows = BadRows::new(OnError::Collect, Some(Box::new(&mut buf)))?;
+        let err = anyhow::anyhow!("line 3: expected 3 fields, found 2");
+        rows.handle(3, "x,\"y\"", err)?;
+        assert_eq!(rows.finish()?, 1);
+        assert_eq!(
+            String::from_utf8(buf)?,
+            "line,reason,raw\n3,\"line 3: expected 3 fields, found 2\",\"x,\"\"y\"\"\"\n"
+        );
+        assert!(BadRows::new(OnError::Collect, None).is_err());
+        Ok(())
+    }
+}
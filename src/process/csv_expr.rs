@@ -0,0 +1,339 @@
+use std::{cmp::Ordering, fmt, str::FromStr};
+
+use anyhow::Result;
+use regex::Regex;
+use serde_json::Value;
+
+use super::csv_writer::Record;
+
+/**
+ * 过滤表达式, 例如:
+ *   Position == "Goalkeeper" && Kit Number > 20
+ * 列名可以直接包含空格, 也可以用 `Kit Number` 括起来
+ * 支持 == != > >= < <= =~(正则) && || ! 和括号
+ */
+#[derive(Debug, Clone)]
+pub enum Expr {
+    Column(String),
+    Literal(Value),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Compare(Box<Expr>, CompareOp, Box<Expr>),
+    Match(Box<Expr>, Regex),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Ident(String),
+    Op(&'static str),
+}
+
+// 长的运算符放在前面, 保证优先匹配
+const OPERATORS: &[&str] = &[
+    "==", "!=", ">=", "<=", "=~", "&&", "||", ">", "<", "!", "(", ")",
+];
+
+impl Expr {
+    pub fn eval(&self, record: &Record) -> Value {
+        match self {
+            Expr::Column(name) => record.get(name).cloned().unwrap_or(Value::Null),
+            Expr::Literal(value) => value.clone(),
+            Expr::Not(expr) => Value::Bool(!truthy(&expr.eval(record))),
+            Expr::And(l, r) => Value::Bool(truthy(&l.eval(record)) && truthy(&r.eval(record))),
+            Expr::Or(l, r) => Value::Bool(truthy(&l.eval(record)) || truthy(&r.eval(record))),
+            Expr::Compare(l, op, r) => {
+                let ord = compare_values(&l.eval(record), &r.eval(record));
+                let ret = match op {
+                    CompareOp::Eq => ord == Some(Ordering::Equal),
+                    CompareOp::Ne => ord != Some(Ordering::Equal),
+                    CompareOp::Lt => ord == Some(Ordering::Less),
+                    CompareOp::Le => matches!(ord, Some(Ordering::Less | Ordering::Equal)),
+                    CompareOp::Gt => ord == Some(Ordering::Greater),
+                    CompareOp::Ge => matches!(ord, Some(Ordering::Greater | Ordering::Equal)),
+                };
+                Value::Bool(ret)
+            }
+            Expr::Match(expr, re) => match expr.eval(record) {
+                Value::Null => Value::Bool(false),
+                Value::String(s) => Value::Bool(re.is_match(&s)),
+                v => Value::Bool(re.is_match(&v.to_string())),
+            },
+        }
+    }
+
+    pub fn is_match(&self, record: &Record) -> bool {
+        truthy(&self.eval(record))
+    }
+
+    /// 表达式中引用的所有列名, 用于提前检查列是否存在
+    pub fn columns(&self) -> Vec<&str> {
+        match self {
+            Expr::Column(name) => vec![name.as_str()],
+            Expr::Literal(_) => vec![],
+            Expr::Not(expr) | Expr::Match(expr, _) => expr.columns(),
+            Expr::And(l, r) | Expr::Or(l, r) | Expr::Compare(l, _, r) => {
+                let mut ret = l.columns();
+                ret.extend(r.columns());
+                ret
+            }
+        }
+    }
+}
+
+impl FromStr for Expr {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let expr = parser.parse_or()?;
+        match parser.tokens.get(parser.pos) {
+            None => Ok(expr),
+            Some(token) => anyhow::bail!("unexpected token {} in expression", token),
+        }
+    }
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(s) => write!(f, "{}", s),
+            Token::Quoted(s) => write!(f, "{:?}", s),
+            Token::Ident(s) => write!(f, "`{}`", s),
+            Token::Op(s) => write!(f, "{:?}", s),
+        }
+    }
+}
+
+/// 空值/false/0/空字符串 为假
+pub fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().is_some_and(|n| n != 0.0),
+        Value::String(s) => !s.is_empty(),
+        _ => true,
+    }
+}
+
+/// 数字之间按数值比较, 字符串和数字比较时尝试把字符串解析为数字, null 只等于 null
+pub fn compare_values(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Null, Value::Null) => Some(Ordering::Equal),
+        (Value::Null, _) | (_, Value::Null) => None,
+        (Value::Number(a), Value::Number(b)) => a.as_f64()?.partial_cmp(&b.as_f64()?),
+        (Value::Number(a), Value::String(b)) => match b.parse::<f64>() {
+            Ok(b) => a.as_f64()?.partial_cmp(&b),
+            Err(_) => Some(a.to_string().as_str().cmp(b.as_str())),
+        },
+        (Value::String(_), Value::Number(_)) => compare_values(b, a).map(Ordering::reverse),
+        (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        (a, b) => Some(a.to_string().cmp(&b.to_string())),
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if c == '"' || c == '\'' || c == '`' {
+            let end = rest[1..]
+                .find(c)
+                .ok_or_else(|| anyhow::anyhow!("unterminated quote in expression"))?;
+            let content = rest[1..end + 1].to_string();
+            tokens.push(if c == '`' {
+                Token::Ident(content)
+            } else {
+                Token::Quoted(content)
+            });
+            rest = &rest[end + 2..];
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else {
+            let end = rest
+                .find(|c: char| c.is_whitespace() || "\"'`=!<>&|()".contains(c))
+                .unwrap_or(rest.len());
+            // 单独出现的 = & | 不是任何运算符的一部分
+            if end == 0 {
+                anyhow::bail!("unexpected character {:?} in expression", c);
+            }
+            tokens.push(Token::Word(rest[..end].to_string()));
+            rest = &rest[end..];
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn next_op(&mut self, op: &'static str) -> bool {
+        if self.tokens.get(self.pos) == Some(&Token::Op(op)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_and()?;
+        while self.next_op("||") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_not()?;
+        while self.next_op("&&") {
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_not()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr> {
+        if self.next_op("!") {
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_compare()
+    }
+
+    fn parse_compare(&mut self) -> Result<Expr> {
+        let left = self.parse_primary()?;
+        let op = match self.tokens.get(self.pos) {
+            Some(Token::Op("==")) => CompareOp::Eq,
+            Some(Token::Op("!=")) => CompareOp::Ne,
+            Some(Token::Op("<")) => CompareOp::Lt,
+            Some(Token::Op("<=")) => CompareOp::Le,
+            Some(Token::Op(">")) => CompareOp::Gt,
+            Some(Token::Op(">=")) => CompareOp::Ge,
+            Some(Token::Op("=~")) => {
+                self.pos += 1;
+                let pattern = match self.parse_primary()? {
+                    Expr::Literal(Value::String(s)) => s,
+                    _ => anyhow::bail!("=~ expects a quoted regex"),
+                };
+                return Ok(Expr::Match(Box::new(left), Regex::new(&pattern)?));
+            }
+            _ => return Ok(left),
+        };
+        self.pos += 1;
+        let right = self.parse_primary()?;
+        Ok(Expr::Compare(Box::new(left), op, Box::new(right)))
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("unexpected end of expression"))?;
+        self.pos += 1;
+        match token {
+            Token::Op("(") => {
+                let expr = self.parse_or()?;
+                if !self.next_op(")") {
+                    anyhow::bail!("missing ) in expression");
+                }
+                Ok(expr)
+            }
+            Token::Quoted(s) => Ok(Expr::Literal(Value::String(s))),
+            Token::Ident(s) => Ok(Expr::Column(s)),
+            Token::Word(word) => {
+                // 相邻的单词组成一个带空格的列名, 例如 Kit Number
+                let mut words = vec![word];
+                while let Some(Token::Word(w)) = self.tokens.get(self.pos) {
+                    words.push(w.clone());
+                    self.pos += 1;
+                }
+                if words.len() > 1 {
+                    return Ok(Expr::Column(words.join(" ")));
+                }
+                let word = words.remove(0);
+                let expr = match word.as_str() {
+                    "true" => Expr::Literal(Value::Bool(true)),
+                    "false" => Expr::Literal(Value::Bool(false)),
+                    "null" => Expr::Literal(Value::Null),
+                    _ => match word.parse::<i64>() {
+                        Ok(n) => Expr::Literal(Value::from(n)),
+                        Err(_) => match word.parse::<f64>() {
+                            Ok(n) if n.is_finite() => Expr::Literal(Value::from(n)),
+                            _ => Expr::Column(word),
+                        },
+                    },
+                };
+                Ok(expr)
+            }
+            Token::Op(op) => anyhow::bail!("unexpected {:?} in expression", op),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn record(value: Value) -> Record {
+        value.as_object().cloned().unwrap()
+    }
+
+    #[test]
+    fn test_expr_eval() -> Result<()> {
+        let player = record(json!({
+            "Name": "Mattia Perin",
+            "Position": "Goalkeeper",
+            "Kit Number": 37,
+            "Nationality": null,
+        }));
+        let cases = [
+            (r#"Position == "Goalkeeper" && Kit Number > 20"#, true),
+            (r#"Position == 'Goalkeeper' && `Kit Number` > 40"#, false),
+            (r#"Kit Number >= 37 || Position != "Goalkeeper""#, true),
+            (r#"!(Kit Number < 10) && Name =~ "^Mattia""#, true),
+            ("Kit Number == 37.0", true),
+            ("Nationality == null", true),
+            ("Nationality", false),
+            (r#"Name < "N""#, true),
+        ];
+        for (s, expected) in cases {
+            let expr: Expr = s.parse()?;
+            assert_eq!(expr.is_match(&player), expected, "{}", s);
+        }
+
+        let expr: Expr = r#"Position == "x" && Kit Number > 1"#.parse()?;
+        assert_eq!(expr.columns(), vec!["Position", "Kit Number"]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_expr_parse_error() {
+        assert!("Position ==".parse::<Expr>().is_err());
+        assert!("(Position == 1".parse::<Expr>().is_err());
+        assert!(r#"Name == "abc"#.parse::<Expr>().is_err());
+        assert!("Name =~ 1".parse::<Expr>().is_err());
+        assert!("x = 1".parse::<Expr>().is_err());
+        assert!("x & y".parse::<Expr>().is_err());
+        assert!("x | y".parse::<Expr>().is_err());
+    }
+}
@@ -0,0 +1,439 @@
+use std::{fmt::Write as _, io::Write};
+
+use anyhow::{Context, Result};
+use chrono::{Duration, NaiveDate, NaiveDateTime};
+use csv::{Writer, WriterBuilder};
+use rand::{seq::SliceRandom, Rng, SeedableRng};
+// ChaCha8 的输出不随 rand 版本变化, 同一个种子总是生成相同的数据
+use rand_chacha::ChaCha8Rng;
+use serde_json::Value;
+
+use crate::{cli::CsvFakeOpts, write_output};
+
+use super::{
+    csv_schema::{ColumnSchema, ColumnType, CsvSchema, FakeKind},
+    gen_pass::{generate_password, CharClasses},
+    gen_policy::PasswordPolicy,
+};
+
+const FIRST_NAMES: &[&str] = &[
+    "Alessandro",
+    "Andrea",
+    "Federico",
+    "Francesco",
+    "Giorgio",
+    "Giulia",
+    "Leonardo",
+    "Lorenzo",
+    "Marco",
+    "Matteo",
+    "Paulo",
+    "Sara",
+    "Sofia",
+    "Chiara",
+    "Wojciech",
+    "Adrien",
+    "Blaise",
+    "Cristiano",
+    "Douglas",
+    "Emre",
+    "Juan",
+    "Mario",
+    "Miralem",
+    "Sami",
+    "Merih",
+    "Aaron",
+    "Danilo",
+    "Rodrigo",
+    "Mattia",
+    "Moise",
+];
+const LAST_NAMES: &[&str] = &[
+    "Rossi",
+    "Russo",
+    "Ferrari",
+    "Esposito",
+    "Bianchi",
+    "Romano",
+    "Colombo",
+    "Ricci",
+    "Marino",
+    "Greco",
+    "Bruno",
+    "Gallo",
+    "Conti",
+    "De Luca",
+    "Costa",
+    "Giordano",
+    "Mancini",
+    "Rizzo",
+    "Lombardi",
+    "Moretti",
+    "Chiellini",
+    "Bonucci",
+    "Pjanic",
+    "Khedira",
+    "Rabiot",
+    "Dybala",
+    "Bernardeschi",
+    "Cuadrado",
+    "Ramsey",
+    "Kean",
+];
+const COUNTRIES: &[&str] = &[
+    "Italy",
+    "France",
+    "Germany",
+    "Spain",
+    "Portugal",
+    "Poland",
+    "Brazil",
+    "Argentina",
+    "Uruguay",
+    "Colombia",
+    "Bosnia-Herzegovina",
+    "Turkey",
+    "Wales",
+    "Netherlands",
+    "Belgium",
+    "Croatia",
+    "Switzerland",
+    "England",
+    "Senegal",
+    "Japan",
+];
+const WORDS: &[&str] = &[
+    "lorem",
+    "ipsum",
+    "dolor",
+    "sit",
+    "amet",
+    "consectetur",
+    "adipiscing",
+    "elit",
+    "sed",
+    "do",
+    "eiusmod",
+    "tempor",
+    "incididunt",
+    "ut",
+    "labore",
+    "et",
+    "dolore",
+    "magna",
+    "aliqua",
+    "enim",
+    "minim",
+    "veniam",
+    "quis",
+    "nostrud",
+    "exercitation",
+    "ullamco",
+    "laboris",
+];
+
+/// 每一列的生成方式, 在生成之前解析好 schema 中的参数
+#[derive(Debug, Clone)]
+enum Faker {
+    Sequence,
+    Integer(i64, i64),
+    Float(f64, f64),
+    Boolean,
+    Choice(Vec<String>),
+    Name,
+    FirstName,
+    LastName,
+    Email,
+    Country,
+    Word,
+    Date(NaiveDate, i64, String),
+    Datetime(NaiveDateTime, i64, String),
+    Uuid,
+    Password(u8),
+}
+
+/// 返回使用的种子, 没有指定 --seed 时随机生成, 便于之后复现
+pub fn process_csv_fake(opts: &CsvFakeOpts) -> Result<u64> {
+    let schema = CsvSchema::load(&opts.schema)?;
+    let seed = opts.seed.unwrap_or_else(rand::random);
+    write_output(&opts.output, opts.force, |output| {
+        let writer = WriterBuilder::new()
+            .delimiter(opts.delimiter)
+            .from_writer(output);
+        fake_csv(&schema, opts.rows, seed, writer)
+    })?;
+    Ok(seed)
+}
+
+/// 逐行生成并写出, 内存占用与行数无关
+pub fn fake_csv<W: Write>(
+    schema: &CsvSchema,
+    rows: usize,
+    seed: u64,
+    mut writer: Writer<W>,
+) -> Result<()> {
+    let fakers = schema
+        .columns
+        .iter()
+        .map(|col| Faker::try_new(col).with_context(|| format!("column {:?}", col.name)))
+        .collect::<Result<Vec<_>>>()?;
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    writer.write_record(schema.columns.iter().map(|col| &col.name))?;
+    let mut row = vec![String::new(); fakers.len()];
+    for i in 1..=rows {
+        for (cell, faker) in row.iter_mut().zip(&fakers) {
+            cell.clear();
+            faker.generate(&mut rng, i, cell)?;
+        }
+        writer.write_record(&row)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+impl Faker {
+    fn try_new(col: &ColumnSchema) -> Result<Self> {
+        let spec = col.fake.clone().unwrap_or_default();
+        let kind = match spec.kind {
+            Some(kind) => kind,
+            None => infer_kind(col),
+        };
+        let faker = match kind {
+            FakeKind::Sequence => Faker::Sequence,
+            FakeKind::Integer => {
+                let min = number(&spec.min, Value::as_i64)?.unwrap_or(1);
+                let max = number(&spec.max, Value::as_i64)?.unwrap_or(100);
+                check_range(min, max)?;
+                Faker::Integer(min, max)
+            }
+            FakeKind::Float => {
+                let min = number(&spec.min, Value::as_f64)?.unwrap_or(0.0);
+                let max = number(&spec.max, Value::as_f64)?.unwrap_or(100.0);
+                check_range(min, max)?;
+                // 区间长度溢出为 inf 时 gen_range 会 panic
+                if !(max - min).is_finite() {
+                    anyhow::bail!("float range {}..={} is too large", min, max);
+                }
+                Faker::Float(min, max)
+            }
+            FakeKind::Boolean => Faker::Boolean,
+            FakeKind::Enum => match &col.values {
+                Some(values) if !values.is_empty() => Faker::Choice(values.clone()),
+                _ => anyhow::bail!("enum requires a non-empty \"enum\" list"),
+            },
+            FakeKind::Name => Faker::Name,
+            FakeKind::FirstName => Faker::FirstName,
+            FakeKind::LastName => Faker::LastName,
+            FakeKind::Email => Faker::Email,
+            FakeKind::Country => Faker::Country,
+            FakeKind::Word => Faker::Word,
+            FakeKind::Date => {
+                let min = date(&spec.min)?.unwrap_or(NaiveDate::from_ymd_opt(1970, 1, 1).unwrap());
+                let max =
+                    date(&spec.max)?.unwrap_or(NaiveDate::from_ymd_opt(2005, 12, 31).unwrap());
+                check_range(min, max)?;
+                let format = spec.format.unwrap_or_else(|| "%Y-%m-%d".to_string());
+                Faker::Date(min, (max - min).num_days(), format)
+            }
+            FakeKind::Datetime => {
+                let min = date(&spec.min)?.unwrap_or(NaiveDate::from_ymd_opt(2020, 1, 1).unwrap());
+                let max =
+                    date(&spec.max)?.unwrap_or(NaiveDate::from_ymd_opt(2025, 12, 31).unwrap());
+                check_range(min, max)?;
+                let format = spec
+                    .format
+                    .unwrap_or_else(|| "%Y-%m-%dT%H:%M:%S".to_string());
+                let seconds = (max - min).num_seconds() + 86399;
+                Faker::Datetime(min.and_time(Default::default()), seconds, format)
+            }
+            FakeKind::Uuid => Faker::Uuid,
+            FakeKind::Password => match spec.length.unwrap_or(16) {
+                // 每类字符至少一个
+                length if length < 4 => anyhow::bail!("password length must be at least 4"),
+                length => Faker::Password(length),
+            },
+        };
+        Ok(faker)
+    }
+
+    fn generate(&self, rng: &mut ChaCha8Rng, row: usize, cell: &mut String) -> Result<()> {
+        let pick = |rng: &mut ChaCha8Rng, items: &[&'static str]| {
+            *items.choose(rng).expect("word lists won't be empty")
+        };
+        match self {
+            Faker::Sequence => write!(cell, "{}", row)?,
+            Faker::Integer(min, max) => write!(cell, "{}", rng.gen_range(*min..=*max))?,
+            Faker::Float(min, max) => write!(cell, "{:.2}", rng.gen_range(*min..=*max))?,
+            Faker::Boolean => write!(cell, "{}", rng.gen_bool(0.5))?,
+            Faker::Choice(values) => cell.push_str(values.choose(rng).expect("checked in try_new")),
+            Faker::Name => {
+                let first = pick(rng, FIRST_NAMES);
+                write!(cell, "{} {}", first, pick(rng, LAST_NAMES))?
+            }
+            Faker::FirstName => cell.push_str(pick(rng, FIRST_NAMES)),
+            Faker::LastName => cell.push_str(pick(rng, LAST_NAMES)),
+            Faker::Email => {
+                let first = pick(rng, FIRST_NAMES).to_lowercase();
+                let last = pick(rng, LAST_NAMES).to_lowercase().replace(' ', "");
+                let n = rng.gen_range(1..100);
+                write!(cell, "{}.{}{}@example.com", first, last, n)?
+            }
+            Faker::Country => cell.push_str(pick(rng, COUNTRIES)),
+            Faker::Word => cell.push_str(pick(rng, WORDS)),
+            Faker::Date(min, days, format) => {
+                let date = *min + Duration::days(rng.gen_range(0..=*days));
+                write!(cell, "{}", date.format(format))?
+            }
+            Faker::Datetime(min, seconds, format) => {
+                let time = *min + Duration::seconds(rng.gen_range(0..=*seconds));
+                write!(cell, "{}", time.format(format))?
+            }
+            Faker::Uuid => {
+                // 随机的 v4 UUID
+                let mut bytes: [u8; 16] = rng.gen();
+                bytes[6] = (bytes[6] & 0x0f) | 0x40;
+                bytes[8] = (bytes[8] & 0x3f) | 0x80;
+                for (i, b) in bytes.iter().enumerate() {
+                    if matches!(i, 4 | 6 | 8 | 10) {
+                        cell.push('-');
+                    }
+                    write!(cell, "{:02x}", b)?;
+                }
+            }
+            Faker::Password(length) => cell.push_str(&generate_password(
+                rng,
+                *length,
+                CharClasses::default(),
+                &PasswordPolicy::default(),
+            )?),
+        }
+        Ok(())
+    }
+}
+
+/// 没有指定 kind 时, 先看 enum 和 type, 字符串列再根据列名猜测
+fn infer_kind(col: &ColumnSchema) -> FakeKind {
+    if col.values.as_ref().is_some_and(|values| !values.is_empty()) {
+        return FakeKind::Enum;
+    }
+    match col.kind {
+        Some(ColumnType::Integer) => return FakeKind::Integer,
+        Some(ColumnType::Float) => return FakeKind::Float,
+        Some(ColumnType::Boolean) => return FakeKind::Boolean,
+        _ => {}
+    }
+    let name = col.name.to_lowercase();
+    let has = |keys: &[&str]| keys.iter().any(|key| name.contains(key));
+    if name == "id" || name.ends_with("_id") {
+        FakeKind::Sequence
+    } else if has(&["uuid", "guid"]) {
+        FakeKind::Uuid
+    } else if has(&["mail"]) {
+        FakeKind::Email
+    } else if has(&["first"]) {
+        FakeKind::FirstName
+    } else if has(&["last", "surname"]) {
+        FakeKind::LastName
+    } else if has(&["name"]) {
+        FakeKind::Name
+    } else if has(&["country", "nationality"]) {
+        FakeKind::Country
+    } else if has(&["password"]) {
+        FakeKind::Password
+    } else if has(&["created", "updated", "time"]) {
+        FakeKind::Datetime
+    } else if has(&["date", "dob", "birth"]) {
+        FakeKind::Date
+    } else {
+        FakeKind::Word
+    }
+}
+
+fn number<T>(value: &Option<Value>, f: impl Fn(&Value) -> Option<T>) -> Result<Option<T>> {
+    value
+        .as_ref()
+        .map(|v| f(v).ok_or_else(|| anyhow::anyhow!("invalid min/max {}", v)))
+        .transpose()
+}
+
+fn date(value: &Option<Value>) -> Result<Option<NaiveDate>> {
+    let Some(value) = value else {
+        return Ok(None);
+    };
+    let date = value
+        .as_str()
+        .and_then(|s| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok())
+        .ok_or_else(|| anyhow::anyhow!("invalid date {}, expected YYYY-MM-DD", value))?;
+    Ok(Some(date))
+}
+
+fn check_range<T: PartialOrd + std::fmt::Display>(min: T, max: T) -> Result<()> {
+    if min > max {
+        anyhow::bail!("min {} is greater than max {}", min, max);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fake(schema: &CsvSchema, rows: usize, seed: u64) -> Result<String> {
+        let mut buf = Vec::new();
+        fake_csv(schema, rows, seed, Writer::from_writer(&mut buf))?;
+        Ok(String::from_utf8(buf)?)
+    }
+
+    #[test]
+    fn test_fake_csv() -> Result<()> {
+        let schema: CsvSchema = serde_yaml::from_str(
+            r#"
+            columns:
+              - { name: id }
+              - { name: Name }
+              - { name: Position, enum: [Goalkeeper, Centre-Back, Centre-Forward] }
+              - { name: DOB, fake: { kind: date, min: "1985-01-01", max: "2001-12-31", format: "%b %d, %Y" } }
+              - { name: Kit Number, type: integer, fake: { min: 1, max: 99 } }
+              - { name: user_uuid }
+              - { name: Email }
+            "#,
+        )?;
+        let content = fake(&schema, 200, 42)?;
+        assert_eq!(content, fake(&schema, 200, 42)?);
+        assert_ne!(content, fake(&schema, 200, 7)?);
+
+        let mut reader = csv::Reader::from_reader(content.as_bytes());
+        let rows = reader.records().collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(rows.len(), 200);
+        for (i, row) in rows.iter().enumerate() {
+            assert_eq!(row[0].parse::<usize>()?, i + 1);
+            assert!(row[1].contains(' '));
+            assert!(["Goalkeeper", "Centre-Back", "Centre-Forward"].contains(&&row[2]));
+            let dob = NaiveDate::parse_from_str(&row[3], "%b %d, %Y")?;
+            assert!((1985..=2001).contains(&chrono::Datelike::year(&dob)));
+            assert!((1..=99).contains(&row[4].parse::<i64>()?));
+            assert_eq!(row[5].len(), 36);
+            assert_eq!(&row[5][14..15], "4");
+            assert!(row[6].ends_with("@example.com"));
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_fake_csv_invalid_schema() -> Result<()> {
+        let invalid = [
+            r#"{ "columns": [{ "name": "a", "fake": { "kind": "enum" } }] }"#,
+            r#"{ "columns": [{ "name": "a", "fake": { "kind": "integer", "min": 10, "max": 1 } }] }"#,
+            r#"{ "columns": [{ "name": "a", "fake": { "kind": "date", "min": "yesterday" } }] }"#,
+            r#"{ "columns": [{ "name": "a", "fake": { "kind": "password", "length": 2 } }] }"#,
+            r#"{ "columns": [{ "name": "a", "fake": { "kind": "float", "min": -1e308, "max": 1e308 } }] }"#,
+        ];
+        for schema in invalid {
+            let schema: CsvSchema = serde_json::from_str(schema)?;
+            assert!(fake(&schema, 1, 0).is_err());
+        }
+        let schema = CsvSchema::load("fixtures/players_schema.json")?;
+        assert_eq!(fake(&schema, 3, 0)?.lines().count(), 4);
+        Ok(())
+    }
+}
@@ -0,0 +1,1086 @@
+use std::{cmp::Ordering, collections::HashMap, str::FromStr};
+
+use anyhow::{Context, Result};
+use regex::Regex;
+use serde_json::Value;
+
+use crate::{cli::CsvQueryOpts, get_reader, CsvReaderOpts};
+
+use super::{
+    csv_convert::{build_csv_reader, read_csv_headers, RecordConverter},
+    csv_expr::{compare_values, CompareOp, Expr},
+    csv_show::Table,
+    csv_writer::{cell_to_string, format_values, Record},
+};
+
+/**
+ * 简化版的 SQL 查询, 例如:
+ *   SELECT Nationality, count(*) AS players FROM players WHERE "Kit Number" > 10
+ *   GROUP BY Nationality ORDER BY players DESC LIMIT 5
+ * 含空格的列名用双引号或反引号括起来, 字符串用单引号
+ * 支持 [LEFT] JOIN ... ON a.x = b.y 连接两张表
+ */
+#[derive(Debug, Clone)]
+pub struct Query {
+    select: Vec<SelectItem>,
+    from: TableRef,
+    join: Option<Join>,
+    filter: Option<Expr>,
+    group_by: Vec<Expr>,
+    order_by: Vec<(Expr, bool)>,
+    limit: Option<usize>,
+}
+
+#[derive(Debug, Clone)]
+enum SelectItem {
+    Wildcard,
+    Expr {
+        expr: Expr,
+        name: String,
+    },
+    Aggregate {
+        func: Aggregate,
+        arg: Option<Expr>,
+        name: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Aggregate {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+}
+
+#[derive(Debug, Clone)]
+struct TableRef {
+    name: String,
+    alias: String,
+}
+
+#[derive(Debug, Clone)]
+struct Join {
+    table: TableRef,
+    left: bool,
+    on: (Expr, Expr),
+}
+
+/// 查询结果, columns 在没有结果行时也能用于输出表头
+#[derive(Debug, Clone, Default)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Record>,
+}
+
+/// 读入内存的表, 每行同时包含 Name 和 alias.Name 两种列名
+struct TableData {
+    alias: String,
+    headers: Vec<String>,
+    rows: Vec<Record>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Ident(String),
+    Str(String),
+    Number(Value),
+    Symbol(&'static str),
+}
+
+// 长的运算符放在前面, 保证优先匹配
+const SYMBOLS: &[&str] = &[
+    "==", "!=", "<>", ">=", "<=", "=", ">", "<", "(", ")", ",", ".", "*",
+];
+
+const KEYWORDS: &[&str] = &[
+    "select", "from", "where", "group", "by", "order", "asc", "desc", "limit", "join", "inner",
+    "left", "outer", "on", "as", "and", "or", "not", "like", "is", "null", "true", "false",
+];
+
+/// 未指定 --format 时输出终端表格
+pub fn process_csv_query(opts: &CsvQueryOpts) -> Result<String> {
+    let result = query_csv(opts)?;
+    if let Some(format) = opts.format {
+        let values = result
+            .rows
+            .into_iter()
+            .map(Value::Object)
+            .collect::<Vec<_>>();
+        return format_values(&values, format);
+    }
+
+    let cells = result
+        .rows
+        .iter()
+        .map(|row| {
+            result
+                .columns
+                .iter()
+                .map(|col| row.get(col).and_then(cell_to_string).unwrap_or_default())
+                .collect()
+        })
+        .collect();
+    // 整列都是数字时右对齐
+    let right_align = result
+        .columns
+        .iter()
+        .map(|col| {
+            let mut values = result.rows.iter().filter_map(|row| row.get(col));
+            values.clone().any(Value::is_number) && values.all(|v| v.is_number() || v.is_null())
+        })
+        .collect();
+    let table = Table {
+        headers: result.columns,
+        rows: cells,
+        right_align,
+    };
+    Ok(table.render(opts.max_width, false))
+}
+
+pub fn query_csv(opts: &CsvQueryOpts) -> Result<QueryResult> {
+    let query: Query = opts.sql.parse()?;
+    query.execute(|name| {
+        let (_, path) = opts
+            .tables
+            .iter()
+            .find(|(table, _)| table.eq_ignore_ascii_case(name))
+            .with_context(|| {
+                let names = opts.tables.iter().map(|(t, _)| t.as_str());
+                format!(
+                    "table {:?} not found, available tables: {}",
+                    name,
+                    names.collect::<Vec<_>>().join(", ")
+                )
+            })?;
+        Ok((path.clone(), opts.reader.clone()))
+    })
+}
+
+impl Query {
+    /// resolve 根据表名返回文件路径和读取选项, 只有查询中用到的表才会被读取
+    pub fn execute(
+        &self,
+        resolve: impl Fn(&str) -> Result<(String, CsvReaderOpts)>,
+    ) -> Result<QueryResult> {
+        let load = |table: &TableRef| {
+            let (path, opts) = resolve(&table.name)?;
+            load_table(&path, &opts, &table.alias)
+        };
+        let left = load(&self.from)?;
+        let right = match &self.join {
+            Some(join) => {
+                if join.table.alias == self.from.alias {
+                    anyhow::bail!("table alias {:?} is used twice", join.table.alias);
+                }
+                Some(load(&join.table)?)
+            }
+            None => None,
+        };
+        let wildcard = wildcard_columns(&left, right.as_ref());
+        let columns = self.output_columns(&wildcard);
+        self.check_columns(&left, right.as_ref(), &columns)?;
+
+        let mut rows = match (&self.join, &right) {
+            (Some(join), Some(right)) => join_tables(&left, right, join)?,
+            _ => left.rows,
+        };
+        if let Some(filter) = &self.filter {
+            rows.retain(|row| filter.is_match(row));
+        }
+
+        // 每行输出和用于排序的上下文 (原始行 + 输出列)
+        let mut output = if self.is_aggregate() {
+            self.aggregate_rows(rows)
+        } else {
+            rows.into_iter()
+                .map(|row| {
+                    let out = self.project(&row, &[], &wildcard);
+                    (out, row)
+                })
+                .collect()
+        };
+
+        if !self.order_by.is_empty() {
+            let mut keyed = output
+                .into_iter()
+                .map(|(out, mut ctx)| {
+                    ctx.extend(out.iter().map(|(k, v)| (k.clone(), v.clone())));
+                    let keys = self
+                        .order_by
+                        .iter()
+                        .map(|(expr, _)| expr.eval(&ctx))
+                        .collect::<Vec<_>>();
+                    (keys, out)
+                })
+                .collect::<Vec<_>>();
+            keyed.sort_by(|(a, _), (b, _)| {
+                a.iter()
+                    .zip(b)
+                    .zip(&self.order_by)
+                    .map(|((a, b), (_, desc))| {
+                        let ord = order_values(a, b);
+                        if *desc {
+                            ord.reverse()
+                        } else {
+                            ord
+                        }
+                    })
+                    .find(|ord| ord.is_ne())
+                    .unwrap_or(Ordering::Equal)
+            });
+            output = keyed
+                .into_iter()
+                .map(|(_, out)| (out, Record::new()))
+                .collect();
+        }
+        if let Some(limit) = self.limit {
+            output.truncate(limit);
+        }
+
+        Ok(QueryResult {
+            columns,
+            rows: output.into_iter().map(|(out, _)| out).collect(),
+        })
+    }
+
+    /// 有聚合时, 非聚合的输出列只能引用 GROUP BY 中的列, 否则结果取决于组内的第一行
+    fn validate(&self) -> Result<()> {
+        if !self.is_aggregate() {
+            return Ok(());
+        }
+        let grouped = self
+            .group_by
+            .iter()
+            .flat_map(|expr| expr.columns())
+            .collect::<Vec<_>>();
+        for item in &self.select {
+            match item {
+                SelectItem::Wildcard => {
+                    anyhow::bail!("SELECT * cannot be used with GROUP BY or aggregates")
+                }
+                SelectItem::Expr { expr, .. } => {
+                    let columns = expr.columns();
+                    if let Some(name) = columns.iter().find(|name| !grouped.contains(name)) {
+                        anyhow::bail!(
+                            "column {:?} must appear in GROUP BY or be used in an aggregate",
+                            name
+                        );
+                    }
+                }
+                SelectItem::Aggregate { .. } => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn is_aggregate(&self) -> bool {
+        !self.group_by.is_empty()
+            || self
+                .select
+                .iter()
+                .any(|item| matches!(item, SelectItem::Aggregate { .. }))
+    }
+
+    /// 输出的列名, * 展开为所有表的列
+    fn output_columns(&self, wildcard: &[(String, String)]) -> Vec<String> {
+        let mut columns = Vec::new();
+        for item in &self.select {
+            match item {
+                SelectItem::Wildcard => {
+                    columns.extend(wildcard.iter().map(|(name, _)| name.clone()))
+                }
+                SelectItem::Expr { name, .. } | SelectItem::Aggregate { name, .. } => {
+                    columns.push(name.clone())
+                }
+            }
+        }
+        columns
+    }
+
+    /// 执行之前检查引用的列是否存在, 避免拼写错误时静默输出 null
+    fn check_columns(
+        &self,
+        left: &TableData,
+        right: Option<&TableData>,
+        columns: &[String],
+    ) -> Result<()> {
+        let exists = |name: &str| {
+            std::iter::once(left)
+                .chain(right)
+                .any(|t| t.has_column(name))
+        };
+        let mut exprs = Vec::new();
+        for item in &self.select {
+            match item {
+                SelectItem::Expr { expr, .. } => exprs.push(expr),
+                SelectItem::Aggregate { arg: Some(arg), .. } => exprs.push(arg),
+                _ => {}
+            }
+        }
+        exprs.extend(&self.filter);
+        exprs.extend(&self.group_by);
+        if let Some(join) = &self.join {
+            exprs.extend([&join.on.0, &join.on.1]);
+        }
+        for name in exprs.iter().flat_map(|expr| expr.columns()) {
+            if !exists(name) {
+                anyhow::bail!("column {:?} not found", name);
+            }
+        }
+        // ORDER BY 还可以使用输出列的别名
+        for name in self.order_by.iter().flat_map(|(expr, _)| expr.columns()) {
+            if !exists(name) && !columns.iter().any(|col| col == name) {
+                anyhow::bail!("column {:?} not found", name);
+            }
+        }
+        Ok(())
+    }
+
+    /// 计算一行的输出, wildcard 为 * 展开后的 (输出列名, 读取列名)
+    fn project(&self, row: &Record, group: &[Record], wildcard: &[(String, String)]) -> Record {
+        let mut out = Record::new();
+        for item in &self.select {
+            match item {
+                SelectItem::Wildcard => {
+                    for (name, key) in wildcard {
+                        let value = row.get(key).cloned().unwrap_or(Value::Null);
+                        out.insert(name.clone(), value);
+                    }
+                }
+                SelectItem::Expr { expr, name } => {
+                    out.insert(name.clone(), expr.eval(row));
+                }
+                SelectItem::Aggregate { func, arg, name } => {
+                    out.insert(name.clone(), aggregate(*func, arg.as_ref(), group));
+                }
+            }
+        }
+        out
+    }
+
+    /// 按 GROUP BY 分组, 组的顺序为第一次出现的顺序; 没有 GROUP BY 时全部行为一组
+    fn aggregate_rows(&self, rows: Vec<Record>) -> Vec<(Record, Record)> {
+        let mut groups: Vec<Vec<Record>> = Vec::new();
+        if self.group_by.is_empty() {
+            groups.push(rows);
+        } else {
+            let mut index: HashMap<Vec<String>, usize> = HashMap::new();
+            for row in rows {
+                let key = self
+                    .group_by
+                    .iter()
+                    .map(|expr| expr.eval(&row).to_string())
+                    .collect::<Vec<_>>();
+                match index.get(&key) {
+                    Some(&i) => groups[i].push(row),
+                    None => {
+                        index.insert(key, groups.len());
+                        groups.push(vec![row]);
+                    }
+                }
+            }
+        }
+        groups
+            .into_iter()
+            .map(|group| {
+                // 非聚合列只引用 GROUP BY 中的列, 组内每行的值都相同
+                let first = group.first().cloned().unwrap_or_default();
+                (self.project(&first, &group, &[]), first)
+            })
+            .collect()
+    }
+}
+
+impl FromStr for Query {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let query = parser.parse_query()?;
+        query.validate()?;
+        Ok(query)
+    }
+}
+
+/// * 展开后的 (输出列名, 读取列名), 右表中与左表重名的列输出为 alias.Name
+fn wildcard_columns(left: &TableData, right: Option<&TableData>) -> Vec<(String, String)> {
+    let mut columns = left
+        .headers
+        .iter()
+        .map(|h| (h.clone(), format!("{}.{}", left.alias, h)))
+        .collect::<Vec<_>>();
+    if let Some(right) = right {
+        for h in &right.headers {
+            let key = format!("{}.{}", right.alias, h);
+            let name = if left.headers.contains(h) {
+                key.clone()
+            } else {
+                h.clone()
+            };
+            columns.push((name, key));
+        }
+    }
+    columns
+}
+
+fn load_table(path: &str, opts: &CsvReaderOpts, alias: &str) -> Result<TableData> {
+    let mut reader = build_csv_reader(get_reader(path)?, opts)?;
+    let headers = read_csv_headers(&mut reader, opts.header)?;
+    let records = reader.records().collect::<Result<Vec<_>, _>>()?;
+    let converter = RecordConverter::new(headers, &records, None);
+    let rows = records
+        .iter()
+        .map(|record| {
+            let converted = converter.convert(record)?;
+            let mut row = Record::new();
+            for (k, v) in &converted {
+                row.insert(format!("{}.{}", alias, k), v.clone());
+            }
+            row.extend(converted);
+            Ok(row)
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(TableData {
+        alias: alias.to_string(),
+        headers: converter.headers().iter().map(String::from).collect(),
+        rows,
+    })
+}
+
+/// 用右表建哈希表做等值连接, 不带表名的重名列保留左表的值
+fn join_tables(left: &TableData, right: &TableData, join: &Join) -> Result<Vec<Record>> {
+    let (a, b) = &join.on;
+    let (left_key, right_key) = if references(b, right) && !references(a, right) {
+        (a, b)
+    } else if references(a, right) && !references(b, right) {
+        (b, a)
+    } else {
+        anyhow::bail!("JOIN condition must compare a column of each table");
+    };
+
+    let mut index: HashMap<String, Vec<&Record>> = HashMap::new();
+    for row in &right.rows {
+        let key = right_key.eval(row);
+        if !key.is_null() {
+            index.entry(join_key(&key)).or_default().push(row);
+        }
+    }
+
+    let mut rows = Vec::new();
+    for row in &left.rows {
+        let key = left_key.eval(row);
+        let matches = match key.is_null() {
+            true => None,
+            false => index.get(&join_key(&key)),
+        };
+        match matches {
+            Some(matches) => {
+                for other in matches {
+                    let mut merged = row.clone();
+                    for (k, v) in other.iter() {
+                        merged.entry(k.clone()).or_insert_with(|| v.clone());
+                    }
+                    rows.push(merged);
+                }
+            }
+            None if join.left => {
+                let mut merged = row.clone();
+                for h in &right.headers {
+                    merged.insert(format!("{}.{}", right.alias, h), Value::Null);
+                    merged.entry(h.clone()).or_insert(Value::Null);
+                }
+                rows.push(merged);
+            }
+            None => {}
+        }
+    }
+    Ok(rows)
+}
+
+// 表达式中的列是否都能在该表中找到
+fn references(expr: &Expr, table: &TableData) -> bool {
+    let columns = expr.columns();
+    !columns.is_empty() && columns.iter().all(|name| table.has_column(name))
+}
+
+impl TableData {
+    /// Name 或 alias.Name
+    fn has_column(&self, name: &str) -> bool {
+        let name = name
+            .strip_prefix(self.alias.as_str())
+            .and_then(|rest| rest.strip_prefix('.'))
+            .unwrap_or(name);
+        self.headers.iter().any(|h| h == name)
+    }
+}
+
+// 与 compare_values 保持一致: 1, 1.0 和 "1" 视为同一个值
+fn join_key(value: &Value) -> String {
+    let number = match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.parse::<f64>().ok(),
+        _ => None,
+    };
+    match (number, value) {
+        (Some(n), _) => n.to_string(),
+        (None, Value::String(s)) => s.clone(),
+        (None, v) => v.to_string(),
+    }
+}
+
+/// 聚合函数忽略 null, sum/avg 只统计能转换为数字的值
+fn aggregate(func: Aggregate, arg: Option<&Expr>, rows: &[Record]) -> Value {
+    let values = rows
+        .iter()
+        .map(|row| arg.map_or(Value::Bool(true), |expr| expr.eval(row)))
+        .filter(|v| !v.is_null());
+    match func {
+        Aggregate::Count => Value::from(values.count()),
+        Aggregate::Min | Aggregate::Max => values
+            .reduce(|a, b| {
+                let ord = compare_values(&a, &b).unwrap_or(Ordering::Equal);
+                match (func, ord) {
+                    (Aggregate::Min, Ordering::Greater) | (Aggregate::Max, Ordering::Less) => b,
+                    _ => a,
+                }
+            })
+            .unwrap_or(Value::Null),
+        Aggregate::Sum | Aggregate::Avg => {
+            let numbers = values
+                .filter_map(|v| match v {
+                    Value::Number(n) => Some(n),
+                    Value::String(s) => s.parse::<serde_json::Number>().ok(),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            if numbers.is_empty() {
+                return Value::Null;
+            }
+            let sum = numbers.iter().filter_map(|n| n.as_f64()).sum::<f64>();
+            match func {
+                Aggregate::Avg => Value::from(sum / numbers.len() as f64),
+                // 全部是整数时结果也是整数
+                _ => match numbers.iter().map(|n| n.as_i64()).sum::<Option<i64>>() {
+                    Some(sum) => Value::from(sum),
+                    None => Value::from(sum),
+                },
+            }
+        }
+    }
+}
+
+/// 排序时 null 排在最后
+fn order_values(a: &Value, b: &Value) -> Ordering {
+    match (a, b) {
+        (Value::Null, Value::Null) => Ordering::Equal,
+        (Value::Null, _) => Ordering::Greater,
+        (_, Value::Null) => Ordering::Less,
+        (a, b) => compare_values(a, b).unwrap_or(Ordering::Equal),
+    }
+}
+
+/// LIKE 中 % 匹配任意字符串, _ 匹配单个字符
+fn like_to_regex(pattern: &str) -> Result<Regex> {
+    let mut re = String::from("^");
+    for c in pattern.chars() {
+        match c {
+            '%' => re.push_str(".*"),
+            '_' => re.push('.'),
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    re.push('$');
+    Ok(Regex::new(&re)?)
+}
+
+impl Aggregate {
+    fn parse(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().as_str() {
+            "count" => Some(Aggregate::Count),
+            "sum" => Some(Aggregate::Sum),
+            "avg" => Some(Aggregate::Avg),
+            "min" => Some(Aggregate::Min),
+            "max" => Some(Aggregate::Max),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Aggregate::Count => "count",
+            Aggregate::Sum => "sum",
+            Aggregate::Avg => "avg",
+            Aggregate::Min => "min",
+            Aggregate::Max => "max",
+        }
+    }
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_start();
+    while let Some(c) = rest.chars().next() {
+        if c == '"' || c == '\'' || c == '`' {
+            let end = rest[1..]
+                .find(c)
+                .ok_or_else(|| anyhow::anyhow!("unterminated quote in query"))?;
+            let content = rest[1..end + 1].to_string();
+            tokens.push(if c == '\'' {
+                Token::Str(content)
+            } else {
+                Token::Ident(content)
+            });
+            rest = &rest[end + 2..];
+        } else if c.is_ascii_digit()
+            || (c == '-' && rest[1..].starts_with(|c: char| c.is_ascii_digit()))
+        {
+            let end = rest[1..]
+                .find(|c: char| !c.is_ascii_digit() && c != '.')
+                .map_or(rest.len(), |i| i + 1);
+            let number = &rest[..end];
+            let value = match number.parse::<i64>() {
+                Ok(n) => Value::from(n),
+                Err(_) => Value::from(
+                    number
+                        .parse::<f64>()
+                        .with_context(|| format!("invalid number {}", number))?,
+                ),
+            };
+            tokens.push(Token::Number(value));
+            rest = &rest[end..];
+        } else if let Some(symbol) = SYMBOLS.iter().find(|s| rest.starts_with(**s)) {
+            tokens.push(Token::Symbol(symbol));
+            rest = &rest[symbol.len()..];
+        } else if c.is_alphanumeric() || c == '_' {
+            let end = rest
+                .find(|c: char| !c.is_alphanumeric() && c != '_')
+                .unwrap_or(rest.len());
+            tokens.push(Token::Word(rest[..end].to_string()));
+            rest = &rest[end..];
+        } else {
+            anyhow::bail!("unexpected character {:?} in query", c);
+        }
+        rest = rest.trim_start();
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next_keyword(&mut self, keyword: &str) -> bool {
+        match self.peek() {
+            Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword) => {
+                self.pos += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<()> {
+        if !self.next_keyword(keyword) {
+            anyhow::bail!("expected {} {}", keyword.to_uppercase(), self.position());
+        }
+        Ok(())
+    }
+
+    fn next_symbol(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<()> {
+        if !self.next_symbol(symbol) {
+            anyhow::bail!("expected {:?} {}", symbol, self.position());
+        }
+        Ok(())
+    }
+
+    // 用于错误信息
+    fn position(&self) -> String {
+        match self.peek() {
+            Some(Token::Word(w) | Token::Ident(w)) => format!("near {:?}", w),
+            Some(Token::Str(s)) => format!("near '{}'", s),
+            Some(Token::Number(n)) => format!("near {}", n),
+            Some(Token::Symbol(s)) => format!("near {:?}", s),
+            None => "at end of query".to_string(),
+        }
+    }
+
+    fn parse_query(&mut self) -> Result<Query> {
+        self.expect_keyword("select")?;
+        let mut select = vec![self.parse_select_item(1)?];
+        while self.next_symbol(",") {
+            select.push(self.parse_select_item(select.len() + 1)?);
+        }
+
+        self.expect_keyword("from")?;
+        let from = self.parse_table_ref()?;
+        let join = if self.next_keyword("join") || self.next_keyword("inner") {
+            self.next_keyword("join");
+            Some(self.parse_join(false)?)
+        } else if self.next_keyword("left") {
+            self.next_keyword("outer");
+            self.expect_keyword("join")?;
+            Some(self.parse_join(true)?)
+        } else {
+            None
+        };
+
+        let filter = match self.next_keyword("where") {
+            true => Some(self.parse_or()?),
+            false => None,
+        };
+        let mut group_by = Vec::new();
+        if self.next_keyword("group") {
+            self.expect_keyword("by")?;
+            group_by.push(self.parse_or()?);
+            while self.next_symbol(",") {
+                group_by.push(self.parse_or()?);
+            }
+        }
+        let mut order_by = Vec::new();
+        if self.next_keyword("order") {
+            self.expect_keyword("by")?;
+            loop {
+                // ORDER BY count(*) 按同名的输出列排序
+                let expr = match self.parse_aggregate()? {
+                    Some((_, _, name)) => Expr::Column(name),
+                    None => self.parse_or()?,
+                };
+                let desc = self.next_keyword("desc");
+                if !desc {
+                    self.next_keyword("asc");
+                }
+                order_by.push((expr, desc));
+                if !self.next_symbol(",") {
+                    break;
+                }
+            }
+        }
+        let limit = match self.next_keyword("limit") {
+            true => match self.tokens.get(self.pos) {
+                Some(Token::Number(n)) if n.is_u64() => {
+                    self.pos += 1;
+                    n.as_u64().map(|n| n as usize)
+                }
+                _ => anyhow::bail!("LIMIT expects a non-negative integer"),
+            },
+            false => None,
+        };
+        if self.peek().is_some() {
+            anyhow::bail!("unexpected token {}", self.position());
+        }
+
+        Ok(Query {
+            select,
+            from,
+            join,
+            filter,
+            group_by,
+            order_by,
+            limit,
+        })
+    }
+
+    fn parse_join(&mut self, left: bool) -> Result<Join> {
+        let table = self.parse_table_ref()?;
+        self.expect_keyword("on")?;
+        let on = match self.parse_or()? {
+            Expr::Compare(a, CompareOp::Eq, b) => (*a, *b),
+            _ => anyhow::bail!("JOIN only supports ON a = b"),
+        };
+        Ok(Join { table, left, on })
+    }
+
+    // 不带别名时使用表名作为别名
+    fn parse_table_ref(&mut self) -> Result<TableRef> {
+        let name = self.parse_identifier()?;
+        let alias = match self.next_keyword("as") {
+            true => self.parse_identifier()?,
+            false => self.parse_alias().unwrap_or_else(|| name.clone()),
+        };
+        Ok(TableRef { name, alias })
+    }
+
+    fn parse_select_item(&mut self, index: usize) -> Result<SelectItem> {
+        if self.next_symbol("*") {
+            return Ok(SelectItem::Wildcard);
+        }
+        let item = match self.parse_aggregate()? {
+            Some((func, arg, name)) => SelectItem::Aggregate { func, arg, name },
+            None => {
+                let expr = self.parse_or()?;
+                let name = match &expr {
+                    Expr::Column(name) => name.clone(),
+                    _ => format!("column{}", index),
+                };
+                SelectItem::Expr { expr, name }
+            }
+        };
+        let alias = match self.next_keyword("as") {
+            true => Some(self.parse_identifier()?),
+            false => self.parse_alias(),
+        };
+        Ok(match (item, alias) {
+            (SelectItem::Expr { expr, .. }, Some(name)) => SelectItem::Expr { expr, name },
+            (SelectItem::Aggregate { func, arg, .. }, Some(name)) => {
+                SelectItem::Aggregate { func, arg, name }
+            }
+            (item, _) => item,
+        })
+    }
+
+    /// count(*) / sum(col) 等, 返回函数, 参数和默认的输出列名
+    fn parse_aggregate(&mut self) -> Result<Option<(Aggregate, Option<Expr>, String)>> {
+        let func = match (self.peek(), self.tokens.get(self.pos + 1)) {
+            (Some(Token::Word(w)), Some(Token::Symbol("("))) => match Aggregate::parse(w) {
+                Some(func) => func,
+                None => anyhow::bail!("unknown function {}", w),
+            },
+            _ => return Ok(None),
+        };
+        self.pos += 2;
+        let arg = match func == Aggregate::Count && self.next_symbol("*") {
+            true => None,
+            false => Some(self.parse_or()?),
+        };
+        self.expect_symbol(")")?;
+        let arg_name = match &arg {
+            None => "*".to_string(),
+            Some(Expr::Column(name)) => name.clone(),
+            Some(Expr::Literal(value)) => value.to_string(),
+            Some(_) => "expr".to_string(),
+        };
+        let name = format!("{}({})", func.name(), arg_name);
+        Ok(Some((func, arg, name)))
+    }
+
+    // 省略 AS 的别名, 不能是关键字
+    fn parse_alias(&mut self) -> Option<String> {
+        match self.peek() {
+            Some(Token::Word(w)) if !is_keyword(w) => {
+                let alias = w.clone();
+                self.pos += 1;
+                Some(alias)
+            }
+            Some(Token::Ident(s)) => {
+                let alias = s.clone();
+                self.pos += 1;
+                Some(alias)
+            }
+            _ => None,
+        }
+    }
+
+    fn parse_identifier(&mut self) -> Result<String> {
+        self.parse_alias()
+            .ok_or_else(|| anyhow::anyhow!("expected identifier {}", self.position()))
+    }
+
+    fn parse_or(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_and()?;
+        while self.next_keyword("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_not()?;
+        while self.next_keyword("and") {
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_not()?));
+        }
+        Ok(expr)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr> {
+        if self.next_keyword("not") {
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_compare()
+    }
+
+    fn parse_compare(&mut self) -> Result<Expr> {
+        let left = self.parse_primary()?;
+        if self.next_keyword("is") {
+            let op = match self.next_keyword("not") {
+                true => CompareOp::Ne,
+                false => CompareOp::Eq,
+            };
+            self.expect_keyword("null")?;
+            let null = Expr::Literal(Value::Null);
+            return Ok(Expr::Compare(Box::new(left), op, Box::new(null)));
+        }
+        let negate = self.next_keyword("not");
+        if self.next_keyword("like") {
+            let pattern = match self.tokens.get(self.pos) {
+                Some(Token::Str(s)) => like_to_regex(s)?,
+                _ => anyhow::bail!("LIKE expects a quoted pattern"),
+            };
+            self.pos += 1;
+            let expr = Expr::Match(Box::new(left), pattern);
+            return Ok(match negate {
+                true => Expr::Not(Box::new(expr)),
+                false => expr,
+            });
+        }
+        if negate {
+            anyhow::bail!("expected LIKE {}", self.position());
+        }
+        let op = match self.peek() {
+            Some(Token::Symbol("=" | "==")) => CompareOp::Eq,
+            Some(Token::Symbol("!=" | "<>")) => CompareOp::Ne,
+            Some(Token::Symbol("<")) => CompareOp::Lt,
+            Some(Token::Symbol("<=")) => CompareOp::Le,
+            Some(Token::Symbol(">")) => CompareOp::Gt,
+            Some(Token::Symbol(">=")) => CompareOp::Ge,
+            _ => return Ok(left),
+        };
+        self.pos += 1;
+        let right = self.parse_primary()?;
+        Ok(Expr::Compare(Box::new(left), op, Box::new(right)))
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr> {
+        let token = self
+            .peek()
+            .cloned()
+            .ok_or_else(|| anyhow::anyhow!("unexpected end of query"))?;
+        let expr = match token {
+            Token::Symbol("(") => {
+                self.pos += 1;
+                let expr = self.parse_or()?;
+                self.expect_symbol(")")?;
+                return Ok(expr);
+            }
+            Token::Str(s) => Expr::Literal(Value::String(s)),
+            Token::Number(n) => Expr::Literal(n),
+            Token::Word(w) if w.eq_ignore_ascii_case("true") => Expr::Literal(Value::Bool(true)),
+            Token::Word(w) if w.eq_ignore_ascii_case("false") => Expr::Literal(Value::Bool(false)),
+            Token::Word(w) if w.eq_ignore_ascii_case("null") => Expr::Literal(Value::Null),
+            Token::Word(_) | Token::Ident(_) => {
+                let mut name = self.parse_identifier()?;
+                // 带表名的列 p.Name
+                if self.next_symbol(".") {
+                    name = format!("{}.{}", name, self.parse_identifier()?);
+                }
+                return Ok(Expr::Column(name));
+            }
+            Token::Symbol(_) => anyhow::bail!("unexpected token {}", self.position()),
+        };
+        self.pos += 1;
+        Ok(expr)
+    }
+}
+
+fn is_keyword(word: &str) -> bool {
+    KEYWORDS.iter().any(|k| k.eq_ignore_ascii_case(word))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(sql: &str) -> Result<QueryResult> {
+        let query: Query = sql.parse()?;
+        query.execute(|name| {
+            let path = match name {
+                "players" => "assets/juventus.csv",
+                "nations" => "fixtures/nations.csv",
+                _ => anyhow::bail!("table {:?} not found", name),
+            };
+            Ok((path.to_string(), CsvReaderOpts::default()))
+        })
+    }
+
+    #[test]
+    fn test_query_group_by() -> Result<()> {
+        let ret = run(
+            "SELECT Nationality, count(*) AS players, max(\"Kit Number\") FROM players \
+             WHERE Position <> 'Goalkeeper' GROUP BY Nationality \
+             ORDER BY players DESC, Nationality LIMIT 3",
+        )?;
+        assert_eq!(ret.columns, ["Nationality", "players", "max(Kit Number)"]);
+        let rows = ret
+            .rows
+            .iter()
+            .map(|row| Value::Object(row.clone()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            rows,
+            vec![
+                serde_json::json!({"Nationality": "Italy", "players": 5, "max(Kit Number)": 33}),
+                serde_json::json!({"Nationality": "Brazil", "players": 3, "max(Kit Number)": 13}),
+                serde_json::json!({"Nationality": "Argentina", "players": 2, "max(Kit Number)": 21}),
+            ]
+        );
+
+        let ret = run("SELECT count(*), avg(`Kit Number`) FROM players WHERE Name LIKE 'M%'")?;
+        assert_eq!(ret.rows[0]["count(*)"], 7);
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_join() -> Result<()> {
+        let ret = run("SELECT p.Name, n.Confederation AS conf FROM players p \
+             LEFT JOIN nations n ON n.Nationality = p.Nationality \
+             WHERE n.Confederation IS NULL OR p.Name = 'Paulo Dybala' ORDER BY `Kit Number`")?;
+        let names = ret
+            .rows
+            .iter()
+            .map(|row| (row["p.Name"].as_str().unwrap(), row["conf"].as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            names,
+            vec![("Paulo Dybala", Some("CONMEBOL")), ("Merih Demiral", None)]
+        );
+
+        let ret =
+            run("SELECT * FROM players JOIN nations ON players.Nationality = nations.Nationality")?;
+        assert_eq!(ret.rows.len(), 26);
+        assert_eq!(ret.columns[5], "nations.Nationality");
+        Ok(())
+    }
+
+    #[test]
+    fn test_query_errors() {
+        assert!(run("SELECT Salary FROM players").is_err());
+        assert!(run("SELECT Name FROM teams").is_err());
+        assert!(run("SELECT * FROM players GROUP BY Position").is_err());
+        let err = "SELECT Name, count(*) FROM players"
+            .parse::<Query>()
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "column \"Name\" must appear in GROUP BY or be used in an aggregate"
+        );
+        assert!("SELECT Name, count(*) FROM players GROUP BY Position"
+            .parse::<Query>()
+            .is_err());
+        assert!("SELECT Name FROM players WHERE".parse::<Query>().is_err());
+        assert!("SELECT Name FROM players LIMIT -1"
+            .parse::<Query>()
+            .is_err());
+        assert!("SELECT median(Name) FROM players".parse::<Query>().is_err());
+        assert!("SELECT Name FROM a JOIN b ON a.x > b.y"
+            .parse::<Query>()
+            .is_err());
+    }
+}
@@ -0,0 +1,249 @@
+use std::{fmt, path::Path};
+
+use anyhow::Result;
+use csv::StringRecord;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::get_content;
+
+/// 列的数据类型, 用于将 CSV 中的字符串转换为 JSON/YAML 中的值
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColumnType {
+    #[serde(alias = "int")]
+    Integer,
+    Float,
+    #[serde(alias = "bool")]
+    Boolean,
+    String,
+}
+
+/**
+ * 描述 CSV 每一列的 schema 文件, 支持 json 和 yaml:
+ * { "columns": [{ "name": "Kit Number", "type": "integer", "required": true }] }
+ * type 用于转换时指定列类型, 其余字段用于 csv validate
+ */
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct CsvSchema {
+    pub columns: Vec<ColumnSchema>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ColumnSchema {
+    pub name: String,
+    #[serde(rename = "type", default)]
+    pub kind: Option<ColumnType>,
+    #[serde(default)]
+    pub required: bool,
+    // 非空值需要匹配的正则
+    #[serde(default)]
+    pub pattern: Option<String>,
+    // 非空值只能是其中之一
+    #[serde(rename = "enum", default)]
+    pub values: Option<Vec<String>>,
+    // csv fake 生成数据的方式, 未指定时根据 enum, type 和列名推断
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fake: Option<FakeSpec>,
+}
+
+/**
+ * 例如 { "kind": "integer", "min": 1, "max": 99 } 或 { "kind": "date", "min": "1985-01-01", "format": "%b %d, %Y" };
+ * min/max 对数字和日期有效, length 对 password 有效
+ */
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct FakeSpec {
+    #[serde(default)]
+    pub kind: Option<FakeKind>,
+    #[serde(default)]
+    pub min: Option<Value>,
+    #[serde(default)]
+    pub max: Option<Value>,
+    #[serde(default)]
+    pub format: Option<String>,
+    #[serde(default)]
+    pub length: Option<u8>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FakeKind {
+    // 从 1 开始递增的编号
+    Sequence,
+    Integer,
+    Float,
+    Boolean,
+    Enum,
+    Name,
+    FirstName,
+    LastName,
+    Email,
+    Country,
+    Date,
+    Datetime,
+    Uuid,
+    Word,
+    Password,
+}
+
+impl ColumnType {
+    /// 推断单个非空值的类型
+    pub fn infer(value: &str) -> Self {
+        // 010 这类以 0 开头的编号需要保留原样
+        let leading_zero = value.len() > 1 && value.starts_with('0') && !value.starts_with("0.");
+        if value.parse::<i64>().is_ok() && !leading_zero {
+            ColumnType::Integer
+        } else if value.parse::<f64>().is_ok_and(f64::is_finite)
+            && value.contains(|c: char| c.is_ascii_digit())
+            && !leading_zero
+        {
+            ColumnType::Float
+        } else if value.eq_ignore_ascii_case("true") || value.eq_ignore_ascii_case("false") {
+            ColumnType::Boolean
+        } else {
+            ColumnType::String
+        }
+    }
+
+    /// 合并同一列中两个值的类型, 整数和浮点数合并为浮点数, 其余不一致的都退化为字符串
+    pub fn merge(self, other: Self) -> Self {
+        match (self, other) {
+            (a, b) if a == b => a,
+            (ColumnType::Integer, ColumnType::Float) | (ColumnType::Float, ColumnType::Integer) => {
+                ColumnType::Float
+            }
+            _ => ColumnType::String,
+        }
+    }
+
+    /// 按类型转换单元格, 空字符串转换为 null
+    pub fn convert(&self, value: &str) -> Result<Value> {
+        if value.is_empty() {
+            return Ok(Value::Null);
+        }
+        let ret = match self {
+            ColumnType::Integer => Value::from(value.parse::<i64>()?),
+            ColumnType::Float => {
+                // NaN 和 inf 在 JSON 中会变成 null, 作为转换错误交给 --on-error 处理
+                let f = value.parse::<f64>()?;
+                if !f.is_finite() {
+                    anyhow::bail!("{:?} is not a finite float", value);
+                }
+                Value::from(f)
+            }
+            ColumnType::Boolean => Value::from(value.to_ascii_lowercase().parse::<bool>()?),
+            ColumnType::String => Value::from(value),
+        };
+        Ok(ret)
+    }
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let s = match self {
+            ColumnType::Integer => "integer",
+            ColumnType::Float => "float",
+            ColumnType::Boolean => "boolean",
+            ColumnType::String => "string",
+        };
+        write!(f, "{}", s)
+    }
+}
+
+impl CsvSchema {
+    pub fn load(path: &str) -> Result<Self> {
+        let content = get_content(path)?;
+        let ext = Path::new(path).extension().and_then(|ext| ext.to_str());
+        let schema = match ext {
+            Some("yaml") | Some("yml") => serde_yaml::from_slice(&content)?,
+            _ => serde_json::from_slice(&content)?,
+        };
+        Ok(schema)
+    }
+
+    pub fn column(&self, name: &str) -> Option<&ColumnSchema> {
+        self.columns.iter().find(|col| col.name == name)
+    }
+
+    /// schema 中为该列声明的类型
+    pub fn column_type(&self, name: &str) -> Option<ColumnType> {
+        self.column(name).and_then(|col| col.kind)
+    }
+}
+
+/// 逐行推断每一列的类型, schema 中声明的列优先使用声明的类型
+pub fn infer_column_types<'a>(
+    headers: &StringRecord,
+    records: impl IntoIterator<Item = &'a StringRecord>,
+    schema: Option<&CsvSchema>,
+) -> Vec<ColumnType> {
+    let mut types: Vec<Option<ColumnType>> = vec![None; headers.len()];
+    for record in records {
+        for (ty, value) in types.iter_mut().zip(record.iter()) {
+            if value.is_empty() {
+                continue;
+            }
+            let inferred = ColumnType::infer(value);
+            *ty = Some(ty.map_or(inferred, |ty| ty.merge(inferred)));
+        }
+    }
+    headers
+        .iter()
+        .zip(types)
+        .map(|(name, ty)| {
+            match schema.and_then(|schema| schema.column_type(name)) {
+                Some(kind) => kind,
+                // 全部为空的列按字符串处理
+                None => ty.unwrap_or(ColumnType::String),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_column_type_infer() {
+        assert_eq!(ColumnType::infer("10"), ColumnType::Integer);
+        assert_eq!(ColumnType::infer("-3"), ColumnType::Integer);
+        assert_eq!(ColumnType::infer("0"), ColumnType::Integer);
+        assert_eq!(ColumnType::infer("1.5"), ColumnType::Float);
+        assert_eq!(ColumnType::infer("0.5"), ColumnType::Float);
+        assert_eq!(ColumnType::infer("True"), ColumnType::Boolean);
+        assert_eq!(ColumnType::infer("007"), ColumnType::String);
+        assert_eq!(ColumnType::infer("NaN"), ColumnType::String);
+        assert_eq!(ColumnType::infer("2019-08-12"), ColumnType::String);
+    }
+
+    #[test]
+    fn test_infer_column_types() -> Result<()> {
+        let headers = StringRecord::from(vec!["a", "b", "c", "d"]);
+        let records = vec![
+            StringRecord::from(vec!["1", "1", "true", ""]),
+            StringRecord::from(vec!["2", "1.5", "x", ""]),
+            StringRecord::from(vec!["", "2", "false", ""]),
+        ];
+        let types = infer_column_types(&headers, &records, None);
+        assert_eq!(
+            types,
+            vec![
+                ColumnType::Integer,
+                ColumnType::Float,
+                ColumnType::String,
+                ColumnType::String
+            ]
+        );
+
+        let schema: CsvSchema =
+            serde_json::from_str(r#"{ "columns": [{ "name": "a", "type": "string" }] }"#)?;
+        let types = infer_column_types(&headers, &records, Some(&schema));
+        assert_eq!(types[0], ColumnType::String);
+        assert_eq!(ColumnType::Integer.convert("")?, Value::Null);
+        assert_eq!(ColumnType::Float.convert("1.5")?, Value::from(1.5));
+        assert!(ColumnType::Float.convert("NaN").is_err());
+        assert!(ColumnType::Float.convert("-inf").is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,212 @@
+use std::collections::VecDeque;
+
+use anyhow::Result;
+use csv::StringRecord;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::{cli::CsvShowOpts, get_reader};
+
+use super::{
+    csv_convert::{build_csv_reader, column_indices, read_csv_headers},
+    csv_schema::{infer_column_types, ColumnType},
+};
+
+/// 在终端中以表格形式预览 csv, 返回渲染好的字符串
+pub fn process_csv_show(opts: &CsvShowOpts) -> Result<String> {
+    let mut reader = build_csv_reader(get_reader(&opts.input)?, &opts.reader)?;
+    let headers = read_csv_headers(&mut reader, opts.reader.header)?;
+    let indices = column_indices(&headers, &opts.columns)?;
+
+    // --tail 时只保留最后 N 行, 内存占用与文件大小无关
+    let mut rows = VecDeque::new();
+    for record in reader.into_records() {
+        if opts.head.is_some_and(|n| rows.len() >= n) {
+            break;
+        }
+        rows.push_back(record?);
+        if opts.tail.is_some_and(|n| rows.len() > n) {
+            rows.pop_front();
+        }
+    }
+
+    let types = infer_column_types(&headers, &rows, None);
+    let table = Table {
+        headers: indices.iter().map(|&i| headers[i].to_string()).collect(),
+        rows: rows.iter().map(|record| select(record, &indices)).collect(),
+        // 数字列右对齐
+        right_align: indices
+            .iter()
+            .map(|&i| matches!(types[i], ColumnType::Integer | ColumnType::Float))
+            .collect(),
+    };
+    Ok(table.render(opts.max_width, opts.plain))
+}
+
+/// 终端表格, stats 等子命令也用它输出
+pub struct Table {
+    pub headers: Vec<String>,
+    pub rows: Vec<Vec<String>>,
+    pub right_align: Vec<bool>,
+}
+
+impl Table {
+    pub fn render(&self, max_width: usize, plain: bool) -> String {
+        let headers = self
+            .headers
+            .iter()
+            .map(|cell| truncate(cell, max_width))
+            .collect::<Vec<_>>();
+        let rows = self
+            .rows
+            .iter()
+            .map(|row| {
+                row.iter()
+                    .map(|cell| truncate(cell, max_width))
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+
+        let mut widths = headers.iter().map(|h| h.width()).collect::<Vec<_>>();
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.width());
+            }
+        }
+
+        let format_row = |row: &[String], right_align: &[bool]| {
+            row.iter()
+                .zip(&widths)
+                .zip(right_align)
+                .map(|((cell, &width), &right)| pad(cell, width, right))
+                .collect::<Vec<_>>()
+        };
+        let left = vec![false; headers.len()];
+        let mut lines = Vec::with_capacity(rows.len() + 4);
+        if plain {
+            // 不输出边框, 方便配合 less/grep 使用
+            lines.push(
+                format_row(&headers, &left)
+                    .join("  ")
+                    .trim_end()
+                    .to_string(),
+            );
+            for row in &rows {
+                let line = format_row(row, &self.right_align).join("  ");
+                lines.push(line.trim_end().to_string());
+            }
+        } else {
+            let border = |l: &str, m: &str, r: &str| {
+                let segments = widths.iter().map(|w| "─".repeat(w + 2)).collect::<Vec<_>>();
+                format!("{}{}{}", l, segments.join(m), r)
+            };
+            lines.push(border("┌", "┬", "┐"));
+            lines.push(format!("│ {} │", format_row(&headers, &left).join(" │ ")));
+            lines.push(border("├", "┼", "┤"));
+            for row in &rows {
+                let cells = format_row(row, &self.right_align);
+                lines.push(format!("│ {} │", cells.join(" │ ")));
+            }
+            lines.push(border("└", "┴", "┘"));
+        }
+        lines.join("\n")
+    }
+}
+
+fn select(record: &StringRecord, indices: &[usize]) -> Vec<String> {
+    indices
+        .iter()
+        .map(|&i| record.get(i).unwrap_or_default().to_string())
+        .collect()
+}
+
+/// 按显示宽度截断 (中文等宽字符占两列), max_width 为 0 时不截断
+fn truncate(cell: &str, max_width: usize) -> String {
+    // 换行和制表符会破坏表格对齐
+    let cell = cell.replace(['\n', '\r', '\t'], " ");
+    if max_width == 0 || cell.width() <= max_width {
+        return cell;
+    }
+    let mut ret = String::new();
+    let mut width = 0;
+    for c in cell.chars() {
+        let w = c.width().unwrap_or(0);
+        if width + w + 1 > max_width {
+            break;
+        }
+        ret.push(c);
+        width += w;
+    }
+    ret.push('…');
+    ret
+}
+
+fn pad(cell: &str, width: usize, right: bool) -> String {
+    let padding = " ".repeat(width.saturating_sub(cell.width()));
+    if right {
+        format!("{}{}", padding, cell)
+    } else {
+        format!("{}{}", cell, padding)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CsvReaderOpts;
+
+    fn show_opts() -> CsvShowOpts {
+        CsvShowOpts {
+            input: "assets/juventus.csv".to_string(),
+            head: None,
+            tail: None,
+            columns: vec![],
+            max_width: 30,
+            plain: false,
+            reader: CsvReaderOpts::default(),
+        }
+    }
+
+    #[test]
+    fn test_process_csv_show() -> Result<()> {
+        let opts = CsvShowOpts {
+            head: Some(2),
+            columns: vec!["Name".to_string(), "Kit Number".to_string()],
+            ..show_opts()
+        };
+        let expected = "\
+┌───────────────────┬────────────┐
+│ Name              │ Kit Number │
+├───────────────────┼────────────┤
+│ Wojciech Szczesny │          1 │
+│ Mattia Perin      │         37 │
+└───────────────────┴────────────┘";
+        assert_eq!(process_csv_show(&opts)?, expected);
+
+        let opts = CsvShowOpts {
+            tail: Some(1),
+            columns: vec!["Name".to_string(), "Position".to_string()],
+            max_width: 8,
+            plain: true,
+            ..show_opts()
+        };
+        assert_eq!(
+            process_csv_show(&opts)?,
+            "Name      Position\nMario M…  Centre-…"
+        );
+
+        let opts = CsvShowOpts {
+            columns: vec!["Salary".to_string()],
+            ..show_opts()
+        };
+        assert!(process_csv_show(&opts).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncate_unicode() {
+        assert_eq!(truncate("北京欢迎你", 5), "北京…");
+        assert_eq!(truncate("北京欢迎你", 10), "北京欢迎你");
+        assert_eq!(truncate("a\nb", 0), "a b");
+        assert_eq!(pad("北京", 6, false), "北京  ");
+    }
+}
@@ -0,0 +1,427 @@
+use std::{
+    cmp::Ordering,
+    collections::BinaryHeap,
+    fs::File,
+    io::{BufReader, Read, Seek, SeekFrom, Write},
+};
+
+use anyhow::Result;
+use csv::{Reader, ReaderBuilder, StringRecord, StringRecordsIntoIter, Writer, WriterBuilder};
+
+use crate::{
+    cli::{CsvDedupOpts, CsvSortOpts, SortKey},
+    get_reader, write_output,
+};
+
+use super::csv_convert::{build_csv_reader, build_csv_writer, column_indices, read_csv_headers};
+
+// 估算内存时每个字段额外计入的开销
+const FIELD_OVERHEAD: usize = 16;
+
+/// 已解析为列下标的排序键
+#[derive(Debug, Clone, Copy)]
+struct KeyIndex {
+    index: usize,
+    desc: bool,
+}
+
+type Compare<'a> = &'a dyn Fn(&StringRecord, &StringRecord) -> Ordering;
+
+/// 归并时堆中的一项, chunk 用于保证排序稳定
+struct HeapEntry<'a> {
+    record: StringRecord,
+    chunk: usize,
+    compare: Compare<'a>,
+}
+
+/// 排好序的记录, 数据可以全部放进内存时不使用临时文件
+enum SortedRecords<'a> {
+    Memory(std::vec::IntoIter<StringRecord>),
+    Merge {
+        readers: Vec<StringRecordsIntoIter<BufReader<File>>>,
+        heap: BinaryHeap<HeapEntry<'a>>,
+    },
+}
+
+pub fn process_csv_sort(opts: &CsvSortOpts) -> Result<()> {
+    let reader = build_csv_reader(get_reader(&opts.input)?, &opts.reader)?;
+    // 先写到临时文件, 输出和输入是同一个文件时不会在读取之前被清空
+    write_output(&opts.output, opts.force, |output| {
+        sort_csv(
+            reader,
+            build_csv_writer(output, &opts.reader),
+            &opts.keys,
+            opts.reader.header,
+            opts.max_memory * 1024 * 1024,
+        )
+    })
+}
+
+/**
+ * 外部归并排序: 读入的数据超过 max_memory 字节时排序后写入临时文件,
+ * 最后对所有临时文件做多路归并, 因此可以处理比内存大的文件
+ */
+pub fn sort_csv<R: Read, W: Write>(
+    mut reader: Reader<R>,
+    mut writer: Writer<W>,
+    keys: &[SortKey],
+    has_header: bool,
+    max_memory: usize,
+) -> Result<()> {
+    let headers = read_csv_headers(&mut reader, has_header)?;
+    let columns = keys.iter().map(|k| k.column.clone()).collect::<Vec<_>>();
+    let keys = column_indices(&headers, &columns)?
+        .into_iter()
+        .zip(keys)
+        .map(|(index, key)| KeyIndex {
+            index,
+            desc: key.desc,
+        })
+        .collect::<Vec<_>>();
+    if has_header {
+        writer.write_record(&headers)?;
+    }
+
+    let compare = |a: &StringRecord, b: &StringRecord| compare_records(a, b, &keys);
+    let records = reader.records().map(|record| Ok(record?));
+    for record in external_sort(records, &compare, max_memory)? {
+        writer.write_record(&record?)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// 按 compare 稳定排序, 超过 max_memory 字节的部分先排序写入临时文件
+fn external_sort<'a, I>(
+    records: I,
+    compare: Compare<'a>,
+    max_memory: usize,
+) -> Result<SortedRecords<'a>>
+where
+    I: Iterator<Item = Result<StringRecord>>,
+{
+    let mut chunks = Vec::new();
+    let mut buffer = Vec::new();
+    let mut size = 0;
+    for record in records {
+        let record = record?;
+        size += record.as_slice().len() + record.len() * FIELD_OVERHEAD;
+        buffer.push(record);
+        if size >= max_memory {
+            chunks.push(spill_chunk(&mut buffer, compare)?);
+            size = 0;
+        }
+    }
+
+    if chunks.is_empty() {
+        buffer.sort_by(compare);
+        return Ok(SortedRecords::Memory(buffer.into_iter()));
+    }
+    if !buffer.is_empty() {
+        chunks.push(spill_chunk(&mut buffer, compare)?);
+    }
+
+    // 临时文件由 spill_chunk 写出, 行的长度可能不同 (--flexible)
+    let mut readers = chunks
+        .into_iter()
+        .map(|file| {
+            ReaderBuilder::new()
+                .has_headers(false)
+                .flexible(true)
+                .from_reader(BufReader::new(file))
+                .into_records()
+        })
+        .collect::<Vec<_>>();
+    let mut heap = BinaryHeap::with_capacity(readers.len());
+    for (chunk, records) in readers.iter_mut().enumerate() {
+        if let Some(record) = records.next() {
+            heap.push(HeapEntry {
+                record: record?,
+                chunk,
+                compare,
+            });
+        }
+    }
+    Ok(SortedRecords::Merge { readers, heap })
+}
+
+impl Iterator for SortedRecords<'_> {
+    type Item = Result<StringRecord>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            SortedRecords::Memory(records) => records.next().map(Ok),
+            SortedRecords::Merge { readers, heap } => {
+                let entry = heap.pop()?;
+                match readers[entry.chunk].next() {
+                    Some(Ok(record)) => heap.push(HeapEntry {
+                        record,
+                        chunk: entry.chunk,
+                        compare: entry.compare,
+                    }),
+                    Some(Err(e)) => return Some(Err(e.into())),
+                    None => {}
+                }
+                Some(Ok(entry.record))
+            }
+        }
+    }
+}
+
+// 临时文件使用默认的 csv 格式, 进程退出时自动删除
+fn spill_chunk(buffer: &mut Vec<StringRecord>, compare: Compare) -> Result<File> {
+    buffer.sort_by(compare);
+    let mut writer = WriterBuilder::new()
+        .flexible(true)
+        .from_writer(tempfile::tempfile()?);
+    for record in buffer.drain(..) {
+        writer.write_record(&record)?;
+    }
+    let mut file = writer.into_inner().map_err(|e| e.into_error())?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(file)
+}
+
+fn compare_records(a: &StringRecord, b: &StringRecord, keys: &[KeyIndex]) -> Ordering {
+    keys.iter()
+        .map(|key| {
+            let ord = compare_fields(
+                a.get(key.index).unwrap_or_default(),
+                b.get(key.index).unwrap_or_default(),
+            );
+            if key.desc {
+                ord.reverse()
+            } else {
+                ord
+            }
+        })
+        .find(|ord| ord.is_ne())
+        .unwrap_or(Ordering::Equal)
+}
+
+/// 数字按数值比较并排在其他值之前, 其余按字符串比较
+fn compare_fields(a: &str, b: &str) -> Ordering {
+    let number = |s: &str| s.parse::<f64>().ok().filter(|n| n.is_finite());
+    match (number(a), number(b)) {
+        (Some(a), Some(b)) => a.total_cmp(&b),
+        (Some(_), None) => Ordering::Less,
+        (None, Some(_)) => Ordering::Greater,
+        (None, None) => a.cmp(b),
+    }
+}
+
+// BinaryHeap 是最大堆, 反转比较结果得到最小的记录
+impl Ord for HeapEntry<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.compare)(&other.record, &self.record).then_with(|| other.chunk.cmp(&self.chunk))
+    }
+}
+
+impl PartialOrd for HeapEntry<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for HeapEntry<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for HeapEntry<'_> {}
+
+/// 返回删除的重复行数
+pub fn process_csv_dedup(opts: &CsvDedupOpts) -> Result<usize> {
+    let reader = build_csv_reader(get_reader(&opts.input)?, &opts.reader)?;
+    let mut removed = 0;
+    write_output(&opts.output, opts.force, |output| {
+        removed = dedup_csv(reader, build_csv_writer(output, &opts.reader), opts)?;
+        Ok(())
+    })?;
+    Ok(removed)
+}
+
+/**
+ * 保留每个 key 第一次出现的行;
+ * --sorted 时只和上一行比较, 否则先给每行加上行号按 (key, 行号) 外部排序,
+ * 保留每组的第一行, 再按行号外部排序恢复原来的顺序, 内存占用不超过 --max-memory
+ */
+pub fn dedup_csv<R: Read, W: Write>(
+    mut reader: Reader<R>,
+    mut writer: Writer<W>,
+    opts: &CsvDedupOpts,
+) -> Result<usize> {
+    let headers = read_csv_headers(&mut reader, opts.reader.header)?;
+    let indices = column_indices(&headers, &opts.keys)?;
+    if opts.reader.header {
+        writer.write_record(&headers)?;
+    }
+    let key = |record: &StringRecord, offset: usize| {
+        indices
+            .iter()
+            .map(|&i| record.get(i + offset).unwrap_or_default().to_string())
+            .collect::<Vec<_>>()
+    };
+
+    let mut removed = 0;
+    if opts.sorted {
+        let mut previous: Option<Vec<String>> = None;
+        for record in reader.records() {
+            let record = record?;
+            let key = key(&record, 0);
+            if previous.as_ref() == Some(&key) {
+                removed += 1;
+            } else {
+                writer.write_record(&record)?;
+            }
+            previous = Some(key);
+        }
+        writer.flush()?;
+        return Ok(removed);
+    }
+
+    // 行号放在第一列, 之后的下标都要加 1
+    let line = |record: &StringRecord| {
+        record
+            .get(0)
+            .and_then(|n| n.parse::<u64>().ok())
+            .unwrap_or_default()
+    };
+    let by_key = |a: &StringRecord, b: &StringRecord| {
+        key(a, 1)
+            .cmp(&key(b, 1))
+            .then_with(|| line(a).cmp(&line(b)))
+    };
+    let by_line = |a: &StringRecord, b: &StringRecord| line(a).cmp(&line(b));
+    let max_memory = opts.max_memory * 1024 * 1024;
+
+    let numbered = reader.records().enumerate().map(|(i, record)| {
+        let mut numbered = StringRecord::from(vec![i.to_string()]);
+        numbered.extend(record?.iter());
+        Ok(numbered)
+    });
+    let mut previous: Option<Vec<String>> = None;
+    let kept = external_sort(numbered, &by_key, max_memory)?.filter_map(|record| {
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => return Some(Err(e)),
+        };
+        let key = key(&record, 1);
+        if previous.as_ref() == Some(&key) {
+            removed += 1;
+            return None;
+        }
+        previous = Some(key);
+        Some(Ok(record))
+    });
+    for record in external_sort(kept, &by_line, max_memory)? {
+        writer.write_record(record?.iter().skip(1))?;
+    }
+    writer.flush()?;
+    Ok(removed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CsvReaderOpts;
+
+    fn sort_str(input: &str, keys: &[&str], max_memory: usize) -> Result<String> {
+        let opts = CsvReaderOpts::default();
+        let keys = keys
+            .iter()
+            .map(|k| k.parse())
+            .collect::<Result<Vec<SortKey>, _>>()?;
+        let mut buf = Vec::new();
+        let reader = build_csv_reader(input.as_bytes(), &opts)?;
+        sort_csv(
+            reader,
+            build_csv_writer(&mut buf, &opts),
+            &keys,
+            true,
+            max_memory,
+        )?;
+        Ok(String::from_utf8(buf)?)
+    }
+
+    #[test]
+    fn test_sort_csv() -> Result<()> {
+        let input = "name,team,kit\nd,b,10\na,a,9\nc,b,\nb,a,10\ne,a,x\n";
+        let expected = "name,team,kit\ne,a,x\nb,a,10\na,a,9\nc,b,\nd,b,10\n";
+        assert_eq!(sort_str(input, &["team", "kit:desc"], 1 << 20)?, expected);
+        // 每行都写入临时文件, 归并结果与内存排序一致
+        assert_eq!(sort_str(input, &["team", "kit:desc"], 1)?, expected);
+
+        let input = std::fs::read_to_string("assets/juventus.csv")?;
+        let in_memory = sort_str(&input, &["Nationality", "Kit Number"], 1 << 20)?;
+        assert_eq!(
+            sort_str(&input, &["Nationality", "Kit Number"], 200)?,
+            in_memory
+        );
+        assert!(in_memory
+            .lines()
+            .nth(1)
+            .unwrap()
+            .starts_with("Paulo Dybala"));
+        assert!(sort_str(&input, &["Salary"], 1 << 20).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_sort_flexible_csv() -> Result<()> {
+        let opts = CsvReaderOpts {
+            flexible: true,
+            ..Default::default()
+        };
+        let keys = vec!["name".parse::<SortKey>()?];
+        let input = "name,team\nc,x,extra\na\nb,y\n";
+        for max_memory in [1 << 20, 1] {
+            let mut buf = Vec::new();
+            let reader = build_csv_reader(input.as_bytes(), &opts)?;
+            let writer = build_csv_writer(&mut buf, &opts);
+            sort_csv(reader, writer, &keys, true, max_memory)?;
+            assert_eq!(String::from_utf8(buf)?, "name,team\na\nb,y\nc,x,extra\n");
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_dedup_csv() -> Result<()> {
+        let input = "name,team\na,x\nb,x\na,x\nc,y\nc,z\n";
+        let dedup_with = |keys: &[&str], sorted: bool, max_memory| -> Result<(String, usize)> {
+            let opts = CsvDedupOpts {
+                input: "-".to_string(),
+                output: "-".to_string(),
+                force: false,
+                keys: keys.iter().map(|k| k.to_string()).collect(),
+                sorted,
+                max_memory,
+                reader: CsvReaderOpts::default(),
+            };
+            let mut buf = Vec::new();
+            let reader = build_csv_reader(input.as_bytes(), &opts.reader)?;
+            let removed = dedup_csv(reader, build_csv_writer(&mut buf, &opts.reader), &opts)?;
+            Ok((String::from_utf8(buf)?, removed))
+        };
+        let dedup = |keys: &[&str], sorted: bool| dedup_with(keys, sorted, 256);
+        assert_eq!(
+            dedup(&[], false)?,
+            ("name,team\na,x\nb,x\nc,y\nc,z\n".into(), 1)
+        );
+        assert_eq!(
+            dedup(&["name"], false)?,
+            ("name,team\na,x\nb,x\nc,y\n".into(), 2)
+        );
+        assert_eq!(
+            dedup(&["team"], true)?,
+            ("name,team\na,x\nc,y\nc,z\n".into(), 2)
+        );
+        // 每行都写入临时文件, 结果仍保持原来的顺序
+        assert_eq!(
+            dedup_with(&["name"], false, 0)?,
+            ("name,team\na,x\nb,x\nc,y\n".into(), 2)
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,358 @@
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::{File, OpenOptions},
+    io::{BufWriter, Write},
+    path::{Path, PathBuf},
+};
+
+use anyhow::Result;
+use regex::{Captures, Regex};
+
+use crate::{cli::CsvSplitOpts, get_reader};
+
+use super::csv_convert::{build_csv_reader, encode_csv_record, read_csv_headers};
+
+// --by 时最多同时打开的文件数, 超过后关闭最久没有写入的文件, 再次写入时以追加方式打开
+const MAX_OPEN_FILES: usize = 64;
+
+/// 拆分方式, 由 --rows/--size/--by 中的一个决定
+#[derive(Debug, Clone)]
+pub enum SplitMode {
+    Rows(usize),
+    Bytes(usize),
+    Column(String),
+}
+
+/// 一个输出文件, bytes 为已写入的字节数, writer 为 None 表示文件暂时被关闭
+struct Chunk {
+    path: PathBuf,
+    writer: Option<BufWriter<File>>,
+    rows: usize,
+    bytes: usize,
+    last_used: u64,
+}
+
+/// 返回按创建顺序排列的所有输出文件
+pub fn process_csv_split(opts: &CsvSplitOpts) -> Result<Vec<PathBuf>> {
+    let mode = match (opts.rows, opts.size, &opts.by) {
+        (Some(0), _, _) | (_, Some(0), _) => anyhow::bail!("chunk size must be positive"),
+        (Some(rows), _, _) => SplitMode::Rows(rows),
+        (_, Some(size), _) => SplitMode::Bytes(size * 1024 * 1024),
+        (_, _, Some(column)) => SplitMode::Column(column.clone()),
+        _ => anyhow::bail!("one of --rows, --size or --by is required"),
+    };
+    let stem = match opts.input.as_str() {
+        "-" => "stdin",
+        input => Path::new(input)
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .unwrap_or("output"),
+    };
+    let template = match (&opts.template, &mode) {
+        (Some(template), _) => template.clone(),
+        (None, SplitMode::Column(_)) => format!("{}_{{value}}.csv", stem),
+        (None, _) => format!("{}_{{index}}.csv", stem),
+    };
+    split_csv(opts, &mode, &opts.dir.join(template))
+}
+
+/**
+ * 按行数, 大小或列值拆分, 每个文件都重复表头;
+ * 文件名模板支持 {index} (从 1 开始, {index:3} 补零到 3 位) 和 {value} (--by 的列值),
+ * 不同的值得到相同的文件名时加上 _2, _3 等后缀; 输出文件已存在时需要 --force 才会覆盖
+ */
+pub fn split_csv(opts: &CsvSplitOpts, mode: &SplitMode, template: &Path) -> Result<Vec<PathBuf>> {
+    let template = template.to_string_lossy();
+    let has_value = template.contains("{value}");
+    if !has_value && !INDEX_RE.is_match(&template) {
+        anyhow::bail!("output template must contain {{index}} or {{value}}");
+    }
+    if has_value && !matches!(mode, SplitMode::Column(_)) {
+        anyhow::bail!("{{value}} can only be used with --by");
+    }
+
+    let reader_opts = &opts.reader;
+    let mut reader = build_csv_reader(get_reader(&opts.input)?, reader_opts)?;
+    let headers = read_csv_headers(&mut reader, reader_opts.header)?;
+    let column = match mode {
+        SplitMode::Column(name) => Some(
+            headers
+                .iter()
+                .position(|h| h == name)
+                .ok_or_else(|| anyhow::anyhow!("column {:?} not found", name))?,
+        ),
+        _ => None,
+    };
+
+    // 先把每行编码到内存中, 这样按大小拆分时可以精确计算字节数
+    let header = match reader_opts.header {
+        true => encode_csv_record(&headers, reader_opts)?,
+        false => Vec::new(),
+    };
+    let mut paths = Vec::new();
+    let mut used = HashSet::new();
+    let mut current: Option<Chunk> = None;
+    let mut by_value: HashMap<String, Chunk> = HashMap::new();
+    // 打开的文件按最近一次写入排序, 值为列值
+    let mut open: BTreeMap<u64, String> = BTreeMap::new();
+    for (tick, record) in reader.records().enumerate() {
+        let tick = tick as u64;
+        let record = record?;
+        let line = encode_csv_record(&record, reader_opts)?;
+        let size = line.len();
+        let chunk = match (mode, column) {
+            (SplitMode::Column(_), Some(i)) => {
+                let value = record.get(i).unwrap_or_default();
+                let closed = by_value.get(value).is_none_or(|c| c.writer.is_none());
+                if closed && open.len() >= MAX_OPEN_FILES {
+                    if let Some((_, oldest)) = open.pop_first() {
+                        by_value.get_mut(&oldest).unwrap().close()?;
+                    }
+                }
+                match by_value.get_mut(value) {
+                    Some(chunk) => {
+                        open.remove(&chunk.last_used);
+                        chunk.reopen()?;
+                    }
+                    None => {
+                        let path = render_template(&template, by_value.len() + 1, Some(value));
+                        let path = unique_path(path, &mut used);
+                        let chunk = Chunk::create(path, &header, opts.force)?;
+                        paths.push(chunk.path.clone());
+                        by_value.insert(value.to_string(), chunk);
+                    }
+                }
+                open.insert(tick, value.to_string());
+                let chunk = by_value.get_mut(value).unwrap();
+                chunk.last_used = tick;
+                chunk
+            }
+            _ => {
+                let full = current.as_ref().is_some_and(|chunk| match mode {
+                    SplitMode::Rows(rows) => chunk.rows >= *rows,
+                    SplitMode::Bytes(bytes) => chunk.rows > 0 && chunk.bytes + size > *bytes,
+                    SplitMode::Column(_) => false,
+                });
+                if full {
+                    current.take().unwrap().close()?;
+                }
+                if current.is_none() {
+                    let path = render_template(&template, paths.len() + 1, None);
+                    let path = unique_path(path, &mut used);
+                    let chunk = Chunk::create(path, &header, opts.force)?;
+                    paths.push(chunk.path.clone());
+                    current = Some(chunk);
+                }
+                current.as_mut().unwrap()
+            }
+        };
+        chunk.write(&line)?;
+    }
+
+    for mut chunk in current.into_iter().chain(by_value.into_values()) {
+        chunk.close()?;
+    }
+    Ok(paths)
+}
+
+impl Chunk {
+    fn create(path: PathBuf, header: &[u8], force: bool) -> Result<Self> {
+        if path.exists() && !force {
+            anyhow::bail!(
+                "{} already exists, use --force to overwrite it",
+                path.display()
+            );
+        }
+        let mut writer = BufWriter::new(File::create(&path)?);
+        writer.write_all(header)?;
+        Ok(Self {
+            path,
+            writer: Some(writer),
+            rows: 0,
+            bytes: header.len(),
+            last_used: 0,
+        })
+    }
+
+    // 被关闭的文件以追加方式重新打开, 不再写表头
+    fn reopen(&mut self) -> Result<()> {
+        if self.writer.is_none() {
+            let file = OpenOptions::new().append(true).open(&self.path)?;
+            self.writer = Some(BufWriter::new(file));
+        }
+        Ok(())
+    }
+
+    fn write(&mut self, line: &[u8]) -> Result<()> {
+        self.reopen()?;
+        self.writer.as_mut().unwrap().write_all(line)?;
+        self.rows += 1;
+        self.bytes += line.len();
+        Ok(())
+    }
+
+    fn close(&mut self) -> Result<()> {
+        if let Some(mut writer) = self.writer.take() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+}
+
+static INDEX_RE: std::sync::LazyLock<Regex> =
+    std::sync::LazyLock::new(|| Regex::new(r"\{index(?::(\d+))?\}").unwrap());
+
+fn render_template(template: &str, index: usize, value: Option<&str>) -> PathBuf {
+    let name = INDEX_RE.replace_all(template, |caps: &Captures| {
+        let width = caps.get(1).map_or(0, |w| w.as_str().parse().unwrap_or(0));
+        format!("{:0width$}", index, width = width)
+    });
+    let name = match value {
+        Some(value) => name.replace("{value}", &sanitize(value)),
+        None => name.to_string(),
+    };
+    PathBuf::from(name)
+}
+
+// a/b 和 a_b 清理后是同一个文件名, 后出现的加上 _2, _3 等后缀
+fn unique_path(path: PathBuf, used: &mut HashSet<PathBuf>) -> PathBuf {
+    let mut candidate = path.clone();
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path.extension().map(|ext| ext.to_string_lossy());
+    let mut n = 1;
+    while used.contains(&candidate) {
+        n += 1;
+        let name = match &ext {
+            Some(ext) => format!("{}_{}.{}", stem, n, ext),
+            None => format!("{}_{}", stem, n),
+        };
+        candidate = path.with_file_name(name);
+    }
+    used.insert(candidate.clone());
+    candidate
+}
+
+// 列值可能包含路径分隔符等不能出现在文件名中的字符
+fn sanitize(value: &str) -> String {
+    if value.is_empty() {
+        return "empty".to_string();
+    }
+    value
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CsvReaderOpts;
+
+    fn split_opts(dir: &Path) -> CsvSplitOpts {
+        CsvSplitOpts {
+            input: "assets/juventus.csv".to_string(),
+            rows: None,
+            size: None,
+            by: None,
+            template: None,
+            dir: dir.to_path_buf(),
+            force: false,
+            reader: CsvReaderOpts::default(),
+        }
+    }
+
+    fn line_counts(paths: &[PathBuf]) -> Result<Vec<usize>> {
+        paths
+            .iter()
+            .map(|p| Ok(std::fs::read_to_string(p)?.lines().count()))
+            .collect()
+    }
+
+    #[test]
+    fn test_split_csv_by_rows() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let opts = CsvSplitOpts {
+            rows: Some(10),
+            ..split_opts(dir.path())
+        };
+        let paths = process_csv_split(&opts)?;
+        assert_eq!(paths[0], dir.path().join("juventus_1.csv"));
+        // 每个文件都有表头
+        assert_eq!(line_counts(&paths)?, vec![11, 11, 8]);
+
+        let opts = CsvSplitOpts {
+            size: Some(1),
+            template: Some("part-{index:3}.csv".to_string()),
+            ..split_opts(dir.path())
+        };
+        let template = dir.path().join("part-{index:3}.csv");
+        let paths = split_csv(&opts, &SplitMode::Bytes(600), &template)?;
+        assert_eq!(paths[1], dir.path().join("part-002.csv"));
+        assert_eq!(line_counts(&paths)?.iter().sum::<usize>(), 27 + paths.len());
+        for path in &paths {
+            assert!(std::fs::metadata(path)?.len() <= 600);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_csv_by_column() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let opts = CsvSplitOpts {
+            by: Some("Position".to_string()),
+            ..split_opts(dir.path())
+        };
+        let paths = process_csv_split(&opts)?;
+        assert_eq!(paths.len(), 10);
+        assert_eq!(paths[0], dir.path().join("juventus_Goalkeeper.csv"));
+        assert_eq!(line_counts(&paths[..1])?, vec![5]);
+
+        let opts = CsvSplitOpts {
+            rows: Some(10),
+            template: Some("{value}.csv".to_string()),
+            ..split_opts(dir.path())
+        };
+        assert!(process_csv_split(&opts).is_err());
+        assert_eq!(sanitize("a/b"), "a_b");
+        Ok(())
+    }
+
+    #[test]
+    fn test_split_csv_collisions() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let input = dir.path().join("teams.csv");
+        // 超过 MAX_OPEN_FILES 个不同的值, a/b 和 a_b 清理后文件名相同
+        let mut content = "team,n\na/b,1\na_b,2\n".to_string();
+        for i in 0..100 {
+            content.push_str(&format!("t{},{}\na/b,{}\n", i, i, i));
+        }
+        std::fs::write(&input, content)?;
+        let opts = CsvSplitOpts {
+            input: input.to_string_lossy().into_owned(),
+            by: Some("team".to_string()),
+            ..split_opts(dir.path())
+        };
+        let paths = process_csv_split(&opts)?;
+        assert_eq!(paths.len(), 102);
+        assert_eq!(paths[0], dir.path().join("teams_a_b.csv"));
+        assert_eq!(paths[1], dir.path().join("teams_a_b_2.csv"));
+        assert_eq!(line_counts(&paths[..3])?, vec![102, 2, 2]);
+        let first = std::fs::read_to_string(&paths[0])?;
+        assert_eq!(first.matches("team,n").count(), 1);
+        assert!(first.ends_with("a/b,99\n"));
+
+        // 已存在的文件需要 --force 才会覆盖
+        assert!(process_csv_split(&opts).is_err());
+        let opts = CsvSplitOpts {
+            force: true,
+            ..opts
+        };
+        assert_eq!(process_csv_split(&opts)?.len(), 102);
+        assert_eq!(line_counts(&paths[..1])?, vec![102]);
+        Ok(())
+    }
+}
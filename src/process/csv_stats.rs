@@ -0,0 +1,246 @@
+use std::collections::HashMap;
+
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::{cli::CsvStatsOpts, get_reader};
+
+use super::{
+    csv_convert::{build_csv_reader, read_csv_headers},
+    csv_schema::ColumnType,
+    csv_show::Table,
+    csv_writer::format_values,
+};
+
+/// 单列的统计结果
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ColumnStats {
+    pub column: String,
+    #[serde(rename = "type")]
+    pub kind: ColumnType,
+    pub count: usize,
+    pub nulls: usize,
+    pub distinct: usize,
+    pub min: Option<f64>,
+    pub max: Option<f64>,
+    pub mean: Option<f64>,
+    pub max_length: usize,
+    pub top: Vec<ValueCount>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ValueCount {
+    pub value: String,
+    pub count: usize,
+}
+
+/// 读取过程中累积的状态, 每个值只看一遍
+#[derive(Debug, Default)]
+struct ColumnProfile {
+    kind: Option<ColumnType>,
+    count: usize,
+    nulls: usize,
+    numbers: usize,
+    min: Option<f64>,
+    max: Option<f64>,
+    sum: f64,
+    max_length: usize,
+    frequencies: HashMap<String, usize>,
+}
+
+impl ColumnProfile {
+    fn add(&mut self, value: &str) {
+        if value.is_empty() {
+            self.nulls += 1;
+            return;
+        }
+        self.count += 1;
+        let kind = ColumnType::infer(value);
+        self.kind = Some(self.kind.map_or(kind, |k| k.merge(kind)));
+        if matches!(kind, ColumnType::Integer | ColumnType::Float) {
+            if let Ok(n) = value.parse::<f64>() {
+                self.min = Some(self.min.map_or(n, |min| min.min(n)));
+                self.max = Some(self.max.map_or(n, |max| max.max(n)));
+                self.sum += n;
+                self.numbers += 1;
+            }
+        }
+        self.max_length = self.max_length.max(value.chars().count());
+        match self.frequencies.get_mut(value) {
+            Some(count) => *count += 1,
+            None => {
+                self.frequencies.insert(value.to_string(), 1);
+            }
+        }
+    }
+
+    fn finish(self, column: String, top: usize) -> ColumnStats {
+        let kind = self.kind.unwrap_or(ColumnType::String);
+        // 只有整列都是数字时才输出 min/max/mean
+        let numeric = matches!(kind, ColumnType::Integer | ColumnType::Float) && self.numbers > 0;
+        let mut values = self
+            .frequencies
+            .iter()
+            .map(|(value, &count)| ValueCount {
+                value: value.clone(),
+                count,
+            })
+            .collect::<Vec<_>>();
+        values.sort_by(|a, b| b.count.cmp(&a.count).then_with(|| a.value.cmp(&b.value)));
+        values.truncate(top);
+        ColumnStats {
+            column,
+            kind,
+            count: self.count,
+            nulls: self.nulls,
+            distinct: self.frequencies.len(),
+            min: self.min.filter(|_| numeric),
+            max: self.max.filter(|_| numeric),
+            mean: numeric.then(|| self.sum / self.numbers as f64),
+            max_length: self.max_length,
+            top: values,
+        }
+    }
+}
+
+pub fn profile_csv(opts: &CsvStatsOpts) -> Result<Vec<ColumnStats>> {
+    let mut reader = build_csv_reader(get_reader(&opts.input)?, &opts.reader)?;
+    let headers = read_csv_headers(&mut reader, opts.reader.header)?;
+    let mut profiles = headers
+        .iter()
+        .map(|_| ColumnProfile::default())
+        .collect::<Vec<_>>();
+    for record in reader.records() {
+        let record = record?;
+        for (profile, value) in profiles.iter_mut().zip(record.iter()) {
+            profile.add(value);
+        }
+    }
+    Ok(headers
+        .iter()
+        .zip(profiles)
+        .map(|(column, profile)| profile.finish(column.to_string(), opts.top))
+        .collect())
+}
+
+/// 未指定 --format 时输出终端表格
+pub fn process_csv_stats(opts: &CsvStatsOpts) -> Result<String> {
+    let stats = profile_csv(opts)?;
+    if let Some(format) = opts.format {
+        let values = stats
+            .iter()
+            .map(serde_json::to_value)
+            .collect::<Result<Vec<_>, _>>()?;
+        return format_values(&values, format);
+    }
+
+    let number = |n: Option<f64>| n.map(|n| round(n).to_string()).unwrap_or_default();
+    let table = Table {
+        headers: [
+            "column", "type", "count", "nulls", "distinct", "min", "max", "mean", "max_len", "top",
+        ]
+        .iter()
+        .map(|s| s.to_string())
+        .collect(),
+        rows: stats
+            .iter()
+            .map(|s| {
+                let top = s
+                    .top
+                    .iter()
+                    .map(|v| format!("{} ({})", v.value, v.count))
+                    .collect::<Vec<_>>();
+                vec![
+                    s.column.clone(),
+                    s.kind.to_string(),
+                    s.count.to_string(),
+                    s.nulls.to_string(),
+                    s.distinct.to_string(),
+                    number(s.min),
+                    number(s.max),
+                    number(s.mean),
+                    s.max_length.to_string(),
+                    top.join(", "),
+                ]
+            })
+            .collect(),
+        right_align: vec![
+            false, false, true, true, true, true, true, true, true, false,
+        ],
+    };
+    Ok(table.render(opts.max_width, false))
+}
+
+// 表格中的平均值保留两位小数
+fn round(n: f64) -> Value {
+    let n = (n * 100.0).round() / 100.0;
+    if n.fract() == 0.0 && n.abs() < i64::MAX as f64 {
+        Value::from(n as i64)
+    } else {
+        Value::from(n)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{cli::OutputFormat, CsvReaderOpts};
+
+    fn stats_opts() -> CsvStatsOpts {
+        CsvStatsOpts {
+            input: "assets/juventus.csv".to_string(),
+            top: 3,
+            format: None,
+            max_width: 60,
+            reader: CsvReaderOpts::default(),
+        }
+    }
+
+    #[test]
+    fn test_profile_csv() -> Result<()> {
+        let stats = profile_csv(&stats_opts())?;
+        assert_eq!(stats.len(), 5);
+
+        let kit = &stats[4];
+        assert_eq!(kit.column, "Kit Number");
+        assert_eq!(kit.kind, ColumnType::Integer);
+        assert_eq!((kit.count, kit.nulls, kit.distinct), (27, 0, 27));
+        assert_eq!((kit.min, kit.max), (Some(1.0), Some(77.0)));
+        assert!((kit.mean.unwrap() - 18.2222).abs() < 1e-3);
+
+        let nationality = &stats[3];
+        assert_eq!(nationality.kind, ColumnType::String);
+        assert_eq!(nationality.min, None);
+        assert_eq!(
+            nationality.top[..2],
+            [
+                ValueCount {
+                    value: "Italy".to_string(),
+                    count: 8
+                },
+                ValueCount {
+                    value: "Brazil".to_string(),
+                    count: 3
+                }
+            ]
+        );
+        assert_eq!(stats[0].max_length, 21);
+        Ok(())
+    }
+
+    #[test]
+    fn test_process_csv_stats_format() -> Result<()> {
+        let opts = CsvStatsOpts {
+            format: Some(OutputFormat::Json),
+            ..stats_opts()
+        };
+        let ret: Vec<Value> = serde_json::from_str(&process_csv_stats(&opts)?)?;
+        assert_eq!(ret[4]["type"], "integer");
+        assert_eq!(ret[4]["max"], 77.0);
+
+        let table = process_csv_stats(&stats_opts())?;
+        assert!(table.contains("│ Kit Number  │ integer │"));
+        Ok(())
+    }
+}
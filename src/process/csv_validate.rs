@@ -0,0 +1,205 @@
+use std::{fmt, io::Read};
+
+use anyhow::Result;
+use csv::Reader;
+use regex::Regex;
+
+use crate::{cli::CsvReaderOpts, get_reader};
+
+use super::{
+    csv_convert::{build_csv_reader, read_csv_headers},
+    csv_schema::{ColumnSchema, CsvSchema},
+};
+
+/// 一处不符合 schema 的单元格, line 和 column 都从 1 开始
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Violation {
+    pub line: u64,
+    pub column: usize,
+    pub name: String,
+    pub message: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ValidationReport {
+    pub rows: usize,
+    pub violations: Vec<Violation>,
+}
+
+/// 预先编译好正则的列规则
+struct ColumnRule<'a> {
+    index: usize,
+    schema: &'a ColumnSchema,
+    pattern: Option<Regex>,
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // 整行的错误 (例如列数不对) 没有列名
+        if self.name.is_empty() {
+            return write!(f, "line {}: {}", self.line, self.message);
+        }
+        write!(
+            f,
+            "line {}, column {} ({}): {}",
+            self.line, self.column, self.name, self.message
+        )
+    }
+}
+
+pub fn process_csv_validate(
+    input: &str,
+    opts: &CsvReaderOpts,
+    schema: &CsvSchema,
+) -> Result<ValidationReport> {
+    // 列数不对的行也要读出来作为错误报告, 而不是中止校验
+    let reader_opts = CsvReaderOpts {
+        flexible: true,
+        ..opts.clone()
+    };
+    let reader = build_csv_reader(get_reader(input)?, &reader_opts)?;
+    validate_csv(reader, opts, schema)
+}
+
+/**
+ * 检查所有行, 收集全部错误而不是遇到第一个就停止;
+ * reader 需要是 flexible 的, 没有 --flexible 时列数与表头不同的行报告为错误
+ */
+pub fn validate_csv<R: Read>(
+    mut reader: Reader<R>,
+    opts: &CsvReaderOpts,
+    schema: &CsvSchema,
+) -> Result<ValidationReport> {
+    let headers = read_csv_headers(&mut reader, opts.header)?;
+    let mut report = ValidationReport::default();
+    let mut rules = Vec::with_capacity(schema.columns.len());
+    for col in &schema.columns {
+        let pattern = col
+            .pattern
+            .as_deref()
+            .map(|p| Regex::new(&format!("^(?:{})$", p)))
+            .transpose()?;
+        match headers.iter().position(|h| h == col.name) {
+            Some(index) => rules.push(ColumnRule {
+                index,
+                schema: col,
+                pattern,
+            }),
+            // 缺少必填列时只在表头报一次
+            None if col.required => report.violations.push(Violation {
+                line: 1,
+                column: 0,
+                name: col.name.clone(),
+                message: "required column is missing".to_string(),
+            }),
+            None => {}
+        }
+    }
+
+    for record in reader.records() {
+        let record = record?;
+        report.rows += 1;
+        let line = record.position().map_or(0, |pos| pos.line());
+        if !opts.flexible && record.len() != headers.len() {
+            report.violations.push(Violation {
+                line,
+                column: 0,
+                name: String::new(),
+                message: format!("expected {} fields, found {}", headers.len(), record.len()),
+            });
+            continue;
+        }
+        for rule in &rules {
+            let value = record.get(rule.index).unwrap_or_default();
+            if let Some(message) = rule.check(value) {
+                report.violations.push(Violation {
+                    line,
+                    column: rule.index + 1,
+                    name: rule.schema.name.clone(),
+                    message,
+                });
+            }
+        }
+    }
+    Ok(report)
+}
+
+impl ColumnRule<'_> {
+    fn check(&self, value: &str) -> Option<String> {
+        if value.is_empty() {
+            return self
+                .schema
+                .required
+                .then(|| "required value is empty".to_string());
+        }
+        if let Some(kind) = self.schema.kind {
+            if kind.convert(value).is_err() {
+                return Some(format!("{:?} is not a valid {}", value, kind));
+            }
+        }
+        if let Some(pattern) = &self.pattern {
+            if !pattern.is_match(value) {
+                return Some(format!("{:?} does not match {:?}", value, pattern.as_str()));
+            }
+        }
+        if let Some(values) = &self.schema.values {
+            if !values.iter().any(|v| v == value) {
+                return Some(format!("{:?} is not one of {:?}", value, values));
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_validate_csv() -> Result<()> {
+        let opts = CsvReaderOpts::default();
+        let schema = CsvSchema::load("fixtures/players_schema.json")?;
+        let report = process_csv_validate("assets/juventus.csv", &opts, &schema)?;
+        assert_eq!(report.rows, 27);
+        assert!(report.violations.is_empty(), "{:?}", report.violations);
+
+        let input = "\
+Name,Position,DOB,Kit Number
+Wojciech Szczesny,Keeper,\"Apr 18, 1990 (29)\",1
+,Goalkeeper,18/04/1990,ten
+Gianluigi Buffon,Keeper
+";
+        let flexible = CsvReaderOpts {
+            flexible: true,
+            ..opts.clone()
+        };
+        let reader = build_csv_reader(input.as_bytes(), &flexible)?;
+        let report = validate_csv(reader, &opts, &schema)?;
+        let violations = report
+            .violations
+            .iter()
+            .map(|v| (v.line, v.column, v.name.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            violations,
+            vec![
+                (1, 0, "Nationality"),
+                (2, 2, "Position"),
+                (3, 1, "Name"),
+                (3, 3, "DOB"),
+                (3, 4, "Kit Number"),
+                (4, 0, ""),
+            ]
+        );
+        assert_eq!(
+            report.violations[4].to_string(),
+            r#"line 3, column 4 (Kit Number): "ten" is not a valid integer"#
+        );
+        assert_eq!(
+            report.violations[5].to_string(),
+            "line 4: expected 4 fields, found 2"
+        );
+        assert_eq!(report.rows, 3);
+        Ok(())
+    }
+}
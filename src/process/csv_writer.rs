@@ -0,0 +1,508 @@
+use std::io::Write;
+
+use anyhow::Result;
+use rust_xlsxwriter::{Format, Workbook};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::cli::OutputFormat;
+
+use super::csv_arrow::{ColumnarWriter, DEFAULT_ROW_GROUP_SIZE};
+
+pub type Record = Map<String, Value>;
+
+/// 逐条写出转换后的记录, 不需要把整个文件读进内存
+pub trait RecordWriter {
+    fn write_record(&mut self, record: &Record) -> Result<()>;
+    /// 写出结尾 (例如 json 的 `]`) 并 flush
+    fn finish(self: Box<Self>) -> Result<()>;
+}
+
+pub struct JsonWriter<W> {
+    writer: W,
+    count: usize,
+}
+
+pub struct NdjsonWriter<W> {
+    writer: W,
+}
+
+pub struct YamlWriter<W> {
+    writer: W,
+    count: usize,
+}
+
+pub struct TomlWriter<W> {
+    writer: W,
+    count: usize,
+}
+
+pub struct XmlWriter<W> {
+    writer: W,
+}
+
+pub struct MarkdownWriter<W> {
+    writer: W,
+    headers: Option<Vec<String>>,
+}
+
+/// --group-by 需要读完所有记录才能输出, 按分组值第一次出现的顺序排列
+pub struct GroupedWriter<W> {
+    writer: W,
+    format: OutputFormat,
+    column: String,
+    groups: Map<String, Value>,
+}
+
+/// xlsx 是 zip 格式, 只能在内存中生成整个工作簿后一次写出
+pub struct XlsxWriter<W> {
+    writer: W,
+    workbook: Workbook,
+    headers: Vec<String>,
+    row: u32,
+}
+
+impl<W: Write> RecordWriter for JsonWriter<W> {
+    fn write_record(&mut self, record: &Record) -> Result<()> {
+        let sep = if self.count == 0 { "[\n" } else { ",\n" };
+        let content = serde_json::to_string_pretty(record)?;
+        write!(self.writer, "{}{}", sep, indent(&content))?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        // 与 serde_json::to_string_pretty(&Vec) 的输出保持一致
+        let end = if self.count == 0 { "[]" } else { "\n]" };
+        writeln!(self.writer, "{}", end)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> RecordWriter for NdjsonWriter<W> {
+    fn write_record(&mut self, record: &Record) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, record)?;
+        writeln!(self.writer)?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> RecordWriter for YamlWriter<W> {
+    // 每条记录输出为一个单元素序列, 拼接起来就是完整的 yaml 序列
+    fn write_record(&mut self, record: &Record) -> Result<()> {
+        let content = serde_yaml::to_string(&[record])?;
+        self.writer.write_all(content.as_bytes())?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        if self.count == 0 {
+            writeln!(self.writer, "[]")?;
+        }
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct TomlDocument {
+    pub rows: Vec<Record>,
+}
+
+impl<W: Write> RecordWriter for TomlWriter<W> {
+    // toml 没有 null, 需要去掉值为 null 的字段, 每条记录输出为一个 [[rows]]
+    fn write_record(&mut self, record: &Record) -> Result<()> {
+        let row = without_nulls(record);
+        let content = toml::to_string(&TomlDocument { rows: vec![row] })?;
+        let sep = if self.count == 0 { "" } else { "\n" };
+        write!(self.writer, "{}{}", sep, content)?;
+        self.count += 1;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+/// 列名可能包含空格, 不能直接作为 xml 标签, 所以用 <field name="..."> 表示每个单元格
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename = "record")]
+pub struct XmlRecord {
+    #[serde(rename = "field", default)]
+    pub fields: Vec<XmlField>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct XmlField {
+    #[serde(rename = "@name")]
+    pub name: String,
+    #[serde(rename = "$text", skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+
+impl<W: Write> XmlWriter<W> {
+    pub fn try_new(mut writer: W) -> Result<Self> {
+        writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(writer, "<records>")?;
+        Ok(Self { writer })
+    }
+}
+
+impl<W: Write> RecordWriter for XmlWriter<W> {
+    fn write_record(&mut self, record: &Record) -> Result<()> {
+        let record = XmlRecord {
+            fields: record
+                .iter()
+                .map(|(name, value)| XmlField {
+                    name: name.clone(),
+                    value: cell_to_string(value),
+                })
+                .collect(),
+        };
+        let mut content = String::new();
+        let mut serializer = quick_xml::se::Serializer::new(&mut content);
+        serializer.indent(' ', 2);
+        record.serialize(serializer)?;
+        writeln!(self.writer, "{}", indent(&content))?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        writeln!(self.writer, "</records>")?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> RecordWriter for MarkdownWriter<W> {
+    // 表头取第一条记录的字段 (serde_json 开启了 preserve_order, 顺序与 csv 一致)
+    fn write_record(&mut self, record: &Record) -> Result<()> {
+        let headers = match &self.headers {
+            Some(headers) => headers,
+            None => {
+                let headers = record.keys().cloned().collect::<Vec<_>>();
+                writeln!(self.writer, "{}", markdown_row(headers.iter()))?;
+                writeln!(
+                    self.writer,
+                    "{}",
+                    markdown_row(headers.iter().map(|_| "---"))
+                )?;
+                self.headers.insert(headers)
+            }
+        };
+        let cells = headers
+            .iter()
+            .map(|h| record.get(h).and_then(cell_to_string).unwrap_or_default());
+        writeln!(self.writer, "{}", markdown_row(cells))?;
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> GroupedWriter<W> {
+    pub fn try_new(writer: W, format: OutputFormat, column: &str) -> Result<Self> {
+        if !matches!(
+            format,
+            OutputFormat::Json | OutputFormat::Yaml | OutputFormat::Toml
+        ) {
+            anyhow::bail!("--group-by does not support {} output", format);
+        }
+        Ok(Self {
+            writer,
+            format,
+            column: column.to_string(),
+            groups: Map::new(),
+        })
+    }
+}
+
+impl<W: Write> RecordWriter for GroupedWriter<W> {
+    fn write_record(&mut self, record: &Record) -> Result<()> {
+        // 使用 --nest 时分组列可能已经被展开, 按 a.b 路径查找
+        let value = record.get(&self.column).or_else(|| {
+            let mut parts = self.column.split('.');
+            let root = record.get(parts.next()?)?;
+            parts.try_fold(root, |node, part| node.get(part))
+        });
+        let Some(value) = value else {
+            anyhow::bail!("group by column {:?} not found", self.column);
+        };
+        let key = cell_to_string(value).unwrap_or_default();
+        let group = self
+            .groups
+            .entry(key)
+            .or_insert_with(|| Value::Array(Vec::new()));
+        if let Value::Array(rows) = group {
+            rows.push(Value::Object(record.clone()));
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        let content = match self.format {
+            OutputFormat::Yaml => serde_yaml::to_string(&self.groups)?,
+            OutputFormat::Toml => {
+                let groups = self
+                    .groups
+                    .iter()
+                    .map(|(k, rows)| {
+                        let rows = rows.as_array().into_iter().flatten();
+                        let rows = rows.filter_map(Value::as_object).map(without_nulls);
+                        (k.clone(), Value::Array(rows.map(Value::Object).collect()))
+                    })
+                    .collect::<Record>();
+                toml::to_string(&groups)?
+            }
+            _ => serde_json::to_string_pretty(&self.groups)? + "\n",
+        };
+        self.writer.write_all(content.as_bytes())?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> XlsxWriter<W> {
+    pub fn try_new(writer: W) -> Result<Self> {
+        let mut workbook = Workbook::new();
+        workbook.add_worksheet();
+        Ok(Self {
+            writer,
+            workbook,
+            headers: Vec::new(),
+            row: 0,
+        })
+    }
+}
+
+impl<W: Write> RecordWriter for XlsxWriter<W> {
+    // 第一行为表头, 后面的记录出现新的列时追加到最右边; 数字和布尔值保留类型
+    fn write_record(&mut self, record: &Record) -> Result<()> {
+        let sheet = self.workbook.worksheet_from_index(0)?;
+        self.row += 1;
+        for (key, value) in record {
+            let col = match self.headers.iter().position(|h| h == key) {
+                Some(col) => col,
+                None => {
+                    self.headers.push(key.clone());
+                    let bold = Format::new().set_bold();
+                    sheet.write_string_with_format(0, self.headers.len() as u16 - 1, key, &bold)?;
+                    self.headers.len() - 1
+                }
+            };
+            let col = col as u16;
+            match value {
+                Value::Null => {}
+                Value::Bool(b) => {
+                    sheet.write_boolean(self.row, col, *b)?;
+                }
+                Value::Number(n) => {
+                    sheet.write_number(self.row, col, n.as_f64().unwrap_or_default())?;
+                }
+                Value::String(s) => {
+                    sheet.write_string(self.row, col, s)?;
+                }
+                v => {
+                    sheet.write_string(self.row, col, v.to_string())?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn finish(mut self: Box<Self>) -> Result<()> {
+        self.workbook.worksheet_from_index(0)?.autofit();
+        let content = self.workbook.save_to_buffer()?;
+        self.writer.write_all(&content)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+pub fn new_record_writer<'a, W: Write + Send + 'a>(
+    writer: W,
+    format: OutputFormat,
+) -> Result<Box<dyn RecordWriter + 'a>> {
+    let writer: Box<dyn RecordWriter + 'a> = match format {
+        OutputFormat::Json => Box::new(JsonWriter { writer, count: 0 }),
+        OutputFormat::Yaml => Box::new(YamlWriter { writer, count: 0 }),
+        OutputFormat::Ndjson => Box::new(NdjsonWriter { writer }),
+        OutputFormat::Toml => Box::new(TomlWriter { writer, count: 0 }),
+        OutputFormat::Xml => Box::new(XmlWriter::try_new(writer)?),
+        OutputFormat::Markdown => Box::new(MarkdownWriter {
+            writer,
+            headers: None,
+        }),
+        OutputFormat::Xlsx => Box::new(XlsxWriter::try_new(writer)?),
+        OutputFormat::Parquet | OutputFormat::Arrow => Box::new(ColumnarWriter::try_new(
+            writer,
+            format,
+            DEFAULT_ROW_GROUP_SIZE,
+        )?),
+    };
+    Ok(writer)
+}
+
+/// 将转换后的记录序列化为指定格式
+pub fn format_values(values: &[Value], format: OutputFormat) -> Result<String> {
+    if let OutputFormat::Xlsx | OutputFormat::Parquet | OutputFormat::Arrow = format {
+        anyhow::bail!(
+            "{} is a binary format and can only be written to a file",
+            format
+        );
+    }
+    let mut buf = Vec::new();
+    let mut writer = new_record_writer(&mut buf, format)?;
+    for record in values.iter().filter_map(Value::as_object) {
+        writer.write_record(record)?;
+    }
+    writer.finish()?;
+    Ok(String::from_utf8(buf)?)
+}
+
+fn markdown_row<S: AsRef<str>>(cells: impl Iterator<Item = S>) -> String {
+    let cells = cells
+        .map(|cell| cell.as_ref().replace('|', "\\|").replace('\n', "<br>"))
+        .collect::<Vec<_>>();
+    format!("| {} |", cells.join(" | "))
+}
+
+pub fn cell_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        v => Some(v.to_string()),
+    }
+}
+
+// 递归去掉值为 null 的字段, --nest 之后 null 可能出现在嵌套对象中
+fn without_nulls(record: &Record) -> Record {
+    record
+        .iter()
+        .filter(|(_, v)| !v.is_null())
+        .map(|(k, v)| match v {
+            Value::Object(map) => (k.clone(), Value::Object(without_nulls(map))),
+            v => (k.clone(), v.clone()),
+        })
+        .collect()
+}
+
+fn indent(content: &str) -> String {
+    content
+        .lines()
+        .map(|line| format!("  {}", line))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{build_csv_reader, get_reader, read_csv_values, CsvReaderOpts};
+
+    #[derive(Debug, Deserialize)]
+    struct XmlRecords {
+        #[serde(rename = "record", default)]
+        records: Vec<XmlRecord>,
+    }
+
+    #[test]
+    fn test_format_values_round_trip() -> Result<()> {
+        let values = {
+            let opts = CsvReaderOpts::default();
+            let mut reader = build_csv_reader(get_reader("assets/juventus.csv")?, &opts)?;
+            read_csv_values(&mut reader, &opts, None)?
+        };
+
+        let content = format_values(&values, OutputFormat::Json)?;
+        assert_eq!(serde_json::from_str::<Vec<Value>>(&content)?, values);
+
+        let content = format_values(&values, OutputFormat::Yaml)?;
+        assert_eq!(serde_yaml::from_str::<Vec<Value>>(&content)?, values);
+
+        let content = format_values(&values, OutputFormat::Ndjson)?;
+        let ret = content
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<Vec<Value>, _>>()?;
+        assert_eq!(ret, values);
+
+        let content = format_values(&values, OutputFormat::Toml)?;
+        let ret: TomlDocument = toml::from_str(&content)?;
+        assert_eq!(
+            ret.rows.into_iter().map(Value::Object).collect::<Vec<_>>(),
+            values
+        );
+
+        let content = format_values(&values, OutputFormat::Xml)?;
+        let ret: XmlRecords = quick_xml::de::from_str(&content)?;
+        assert_eq!(ret.records.len(), values.len());
+        assert_eq!(ret.records[0].fields[4].name, "Kit Number");
+        assert_eq!(ret.records[0].fields[4].value.as_deref(), Some("1"));
+
+        let content = format_values(&values, OutputFormat::Markdown)?;
+        let lines = content.lines().collect::<Vec<_>>();
+        assert_eq!(lines.len(), values.len() + 2);
+        assert_eq!(
+            lines[0],
+            "| Name | Position | DOB | Nationality | Kit Number |"
+        );
+        assert_eq!(
+            lines[2],
+            "| Wojciech Szczesny | Goalkeeper | Apr 18, 1990 (29) | Poland | 1 |"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_grouped_writer() -> Result<()> {
+        let rows = serde_json::json!([
+            {"Name": "Wojciech Szczesny", "Position": "Goalkeeper", "club": {"city": "Turin"}},
+            {"Name": "Leonardo Bonucci", "Position": "Centre-Back", "club": {"city": null}},
+            {"Name": "Mattia Perin", "Position": "Goalkeeper", "club": {"city": "Turin"}},
+        ]);
+        let group = |format, column| -> Result<String> {
+            let mut buf = Vec::new();
+            let mut writer = Box::new(GroupedWriter::try_new(&mut buf, format, column)?);
+            for row in rows.as_array().unwrap() {
+                writer.write_record(row.as_object().unwrap())?;
+            }
+            writer.finish()?;
+            Ok(String::from_utf8(buf)?)
+        };
+
+        let ret: Value = serde_json::from_str(&group(OutputFormat::Json, "Position")?)?;
+        let groups = ret.as_object().unwrap();
+        assert_eq!(
+            groups.keys().collect::<Vec<_>>(),
+            ["Goalkeeper", "Centre-Back"]
+        );
+        assert_eq!(groups["Goalkeeper"][1]["Name"], "Mattia Perin");
+
+        let ret: Value = serde_yaml::from_str(&group(OutputFormat::Yaml, "club.city")?)?;
+        assert_eq!(ret["Turin"].as_array().map(Vec::len), Some(2));
+        assert_eq!(ret[""][0]["Name"], "Leonardo Bonucci");
+
+        let ret: toml::Value = toml::from_str(&group(OutputFormat::Toml, "Position")?)?;
+        assert_eq!(
+            ret["Centre-Back"][0].get("club"),
+            Some(&toml::Value::Table(Default::default()))
+        );
+
+        assert!(group(OutputFormat::Json, "Age").is_err());
+        assert!(GroupedWriter::try_new(Vec::new(), OutputFormat::Xml, "Position").is_err());
+        Ok(())
+    }
+}
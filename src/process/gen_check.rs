@@ -0,0 +1,137 @@
+use std::io::{BufRead, BufReader, Read};
+
+use anyhow::Result;
+use serde::Serialize;
+use zxcvbn::zxcvbn;
+
+use crate::{cli::GenPassCheckOpts, get_reader};
+
+use super::gen_policy::PasswordPolicy;
+
+/// 一个候选密码的检查结果, 不包含密码本身, 用行号定位
+#[derive(Debug, Clone, Serialize)]
+pub struct PasswordCheck {
+    pub line: usize,
+    pub length: usize,
+    pub zxcvbn_score: u8,
+    pub crack_times: CrackTimes,
+    pub warning: Option<String>,
+    pub suggestions: Vec<String>,
+    pub violations: Vec<String>,
+    pub passed: bool,
+}
+
+/// zxcvbn 在不同攻击场景下估算的破解时间
+#[derive(Debug, Clone, Serialize)]
+pub struct CrackTimes {
+    pub online_throttling: String,
+    pub online_no_throttling: String,
+    pub offline_slow_hashing: String,
+    pub offline_fast_hashing: String,
+}
+
+impl PasswordCheck {
+    pub fn new(
+        line: usize,
+        password: &str,
+        policy: Option<&PasswordPolicy>,
+        min_score: u8,
+    ) -> Self {
+        let estimate = zxcvbn(password, &[]);
+        let times = estimate.crack_times();
+        let crack_times = CrackTimes {
+            online_throttling: times.online_throttling_100_per_hour().to_string(),
+            online_no_throttling: times.online_no_throttling_10_per_second().to_string(),
+            offline_slow_hashing: times.offline_slow_hashing_1e4_per_second().to_string(),
+            offline_fast_hashing: times.offline_fast_hashing_1e10_per_second().to_string(),
+        };
+        let (warning, suggestions) = match estimate.feedback() {
+            Some(feedback) => (
+                feedback.warning().map(|w| w.to_string()),
+                feedback
+                    .suggestions()
+                    .iter()
+                    .map(|s| s.to_string())
+                    .collect(),
+            ),
+            None => (None, Vec::new()),
+        };
+        let violations = policy
+            .map(|policy| policy.violations(password))
+            .unwrap_or_default();
+        let zxcvbn_score = estimate.score().into();
+        Self {
+            line,
+            length: password.chars().count(),
+            zxcvbn_score,
+            crack_times,
+            warning,
+            suggestions,
+            passed: zxcvbn_score >= min_score && violations.is_empty(),
+            violations,
+        }
+    }
+}
+
+pub fn process_genpass_check(opts: &GenPassCheckOpts) -> Result<Vec<PasswordCheck>> {
+    let policy = opts
+        .policy
+        .as_deref()
+        .map(PasswordPolicy::load)
+        .transpose()?;
+    let reader = get_reader(&opts.input)?;
+    check_passwords(reader, policy.as_ref(), opts.min_score)
+}
+
+/// 每行一个候选密码, 忽略空行
+pub fn check_passwords<R: Read>(
+    reader: R,
+    policy: Option<&PasswordPolicy>,
+    min_score: u8,
+) -> Result<Vec<PasswordCheck>> {
+    let mut checks = Vec::new();
+    for (i, line) in BufReader::new(reader).lines().enumerate() {
+        // lines 会去掉行尾的 \n 和 \r\n
+        let password = line?;
+        if password.is_empty() {
+            continue;
+        }
+        checks.push(PasswordCheck::new(i + 1, &password, policy, min_score));
+    }
+    Ok(checks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_passwords() -> Result<()> {
+        let policy = PasswordPolicy::load("fixtures/policy.yaml")?;
+        let input = "password\r\n\nCorrect-Horse-Battery-9\nrP4%kw_Z8mQ!2xd9\n";
+        let checks = check_passwords(input.as_bytes(), Some(&policy), 3)?;
+        assert_eq!(
+            checks.iter().map(|c| c.line).collect::<Vec<_>>(),
+            vec![1, 3, 4]
+        );
+
+        let weak = &checks[0];
+        assert_eq!(weak.length, 8);
+        assert_eq!(weak.zxcvbn_score, 0);
+        assert_eq!(weak.crack_times.offline_fast_hashing, "less than a second");
+        assert!(weak.warning.is_some());
+        assert!(weak
+            .violations
+            .contains(&"contains forbidden substring \"password\"".to_string()));
+        assert!(!weak.passed);
+
+        // 强度足够但不满足策略: 只有一个数字
+        assert_eq!(checks[1].zxcvbn_score, 4);
+        assert!(!checks[1].passed);
+        assert!(checks[2].passed, "{:?}", checks[2]);
+
+        let checks = check_passwords("Correct-Horse-Battery-9".as_bytes(), None, 3)?;
+        assert!(checks[0].passed);
+        Ok(())
+    }
+}
@@ -1,49 +1,309 @@
 use anyhow::Result;
-use rand::seq::SliceRandom;
+use rand::{seq::SliceRandom, Rng};
+use serde::Serialize;
+use zxcvbn::zxcvbn;
 
-const UPPER: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ";
-const LOWER: &[u8] = b"abcdefghjklmnpqrstuvwxyz";
-const NUMBER: &[u8] = b"23456789";
-const SYMBOL: &[u8] = b"!@#$%^&*_";
+use super::gen_policy::PasswordPolicy;
+
+// 去掉了容易混淆的 I, O, i, o, 0, 1
+pub const UPPER: &[u8] = b"ABCDEFGHJKLMNPQRSTUVWXYZ";
+pub const LOWER: &[u8] = b"abcdefghjklmnpqrstuvwxyz";
+pub const NUMBER: &[u8] = b"23456789";
+pub const SYMBOL: &[u8] = b"!@#$%^&*_";
+
+// 没有指定 --length 时的密码长度
+pub const DEFAULT_LENGTH: u8 = 16;
+
+// 按策略生成时最多尝试的次数
+const MAX_ATTEMPTS: usize = 1000;
+
+/// 生成密码时启用的字符种类, 默认全部启用
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CharClasses {
+    pub upper: bool,
+    pub lower: bool,
+    pub number: bool,
+    pub symbol: bool,
+}
+
+/// --format json 时输出的密码信息
+#[derive(Debug, Clone, Serialize)]
+pub struct PasswordInfo {
+    pub password: String,
+    pub length: usize,
+    pub entropy_bits: f64,
+    pub zxcvbn_score: u8,
+    // 按离线慢哈希 (每秒 1e4 次) 估算的破解时间
+    pub crack_time: String,
+}
+
+impl Default for CharClasses {
+    fn default() -> Self {
+        Self {
+            upper: true,
+            lower: true,
+            number: true,
+            symbol: true,
+        }
+    }
+}
+
+impl CharClasses {
+    /// (名称, 是否启用, 字符集, 策略要求的最少次数)
+    fn sets(self, policy: &PasswordPolicy) -> [(&'static str, bool, &[u8], u8); 4] {
+        [
+            ("uppercase", self.upper, UPPER, policy.min_uppercase),
+            ("lowercase", self.lower, LOWER, policy.min_lowercase),
+            ("number", self.number, NUMBER, policy.min_number),
+            (
+                "symbol",
+                self.symbol,
+                policy.symbols.as_bytes(),
+                policy.min_symbol,
+            ),
+        ]
+    }
+
+    /// 每种启用的字符至少出现 max(1, 策略中的最少次数) 次, 密码长度不能小于它们的和
+    pub fn required_length(self, policy: &PasswordPolicy) -> usize {
+        self.sets(policy)
+            .iter()
+            .filter(|(_, enabled, chars, _)| *enabled && !chars.is_empty())
+            .map(|(_, _, _, min)| (*min).max(1) as usize)
+            .sum()
+    }
+}
+
+impl PasswordInfo {
+    pub fn new(password: String, entropy_bits: f64) -> Self {
+        let estimate = zxcvbn(&password, &[]);
+        let crack_time = estimate
+            .crack_times()
+            .offline_slow_hashing_1e4_per_second()
+            .to_string();
+        Self {
+            length: password.chars().count(),
+            entropy_bits,
+            zxcvbn_score: estimate.score().into(),
+            crack_time,
+            password,
+        }
+    }
+}
 
 pub fn process_genpass(
     length: u8,
-    upper: bool,
-    lower: bool,
-    number: bool,
-    special: bool,
+    classes: CharClasses,
+    policy: &PasswordPolicy,
 ) -> Result<String> {
-    let mut rng = rand::thread_rng();
-    let mut password = Vec::new();
-    let mut chars = Vec::new();
-    // 这里用UPPER.choose获得的是&u8, 需要*UPPER才能获得u8
-    if upper {
-        chars.extend_from_slice(UPPER);
-        password.push(*UPPER.choose(&mut rng).expect("UPPER won't be empty"));
+    generate_password(&mut rand::thread_rng(), length, classes, policy)
+}
+
+/**
+ * 使用指定的随机数生成器, csv fake 用固定种子的生成器得到可复现的结果;
+ * 每种启用的字符至少出现 max(1, 策略中的最少次数) 次, 符号从策略允许的符号中选取,
+ * 不满足连续重复和禁用子串规则的结果会被丢弃重新生成
+ */
+pub fn generate_password<R: Rng + ?Sized>(
+    rng: &mut R,
+    length: u8,
+    classes: CharClasses,
+    policy: &PasswordPolicy,
+) -> Result<String> {
+    let sets = classes.sets(policy);
+    if let Some((name, ..)) = sets
+        .iter()
+        .find(|(_, enabled, chars, min)| *min > 0 && (!enabled || chars.is_empty()))
+    {
+        anyhow::bail!("policy requires {} characters, but they are disabled", name);
     }
-    if lower {
-        chars.extend_from_slice(LOWER);
-        password.push(*LOWER.choose(&mut rng).expect("LOWER won't be empty"));
+    let required = classes.required_length(policy);
+    let classes = sets
+        .into_iter()
+        .filter(|(_, enabled, chars, _)| *enabled && !chars.is_empty())
+        .map(|(_, _, chars, min)| (chars, min.max(1) as usize))
+        .collect::<Vec<_>>();
+    if classes.is_empty() {
+        anyhow::bail!("at least one character class must be enabled");
     }
-    if number {
-        chars.extend_from_slice(NUMBER);
-        password.push(*NUMBER.choose(&mut rng).expect("NUMBER won't be empty"));
+    if (length as usize) < required {
+        anyhow::bail!(
+            "password length must be at least {} to include every enabled character class",
+            required
+        );
     }
-    if special {
-        chars.extend_from_slice(SYMBOL);
-        password.push(*SYMBOL.choose(&mut rng).expect("SYMBOL won't be empty"));
+    if length < policy.min_length || policy.max_length.is_some_and(|max| length > max) {
+        anyhow::bail!("password length {} is not allowed by the policy", length);
+    }
+    let chars = classes
+        .iter()
+        .flat_map(|(chars, _)| chars.iter().copied())
+        .collect::<Vec<_>>();
+
+    for _ in 0..MAX_ATTEMPTS {
+        let mut password = Vec::new();
+        // 这里用chars.choose获得的是&u8, 需要*才能获得u8
+        for (class, min) in &classes {
+            for _ in 0..*min {
+                password.push(*class.choose(rng).expect("class won't be empty"));
+            }
+        }
+        for _ in password.len()..length as usize {
+            let c = chars
+                .choose(rng)
+                .expect("chars won't be empty in this context");
+            password.push(*c);
+        }
+
+        password.shuffle(rng);
+
+        // String原生支持{} 不需要{:?}
+        let password = String::from_utf8(password)?;
+        if policy.violations(&password).is_empty() {
+            return Ok(password);
+        }
+    }
+    anyhow::bail!(
+        "failed to generate a password satisfying the policy after {} attempts",
+        MAX_ATTEMPTS
+    )
+}
+
+/// 按字符集大小估算的熵, 忽略了每种字符至少出现一次和策略规则带来的微小损失
+pub fn password_entropy(length: u8, classes: CharClasses, policy: &PasswordPolicy) -> f64 {
+    let pool = [
+        (classes.upper, UPPER),
+        (classes.lower, LOWER),
+        (classes.number, NUMBER),
+        (classes.symbol, policy.symbols.as_bytes()),
+    ]
+    .iter()
+    .filter(|(enabled, _)| *enabled)
+    .map(|(_, chars)| chars.len())
+    .sum::<usize>();
+    match pool {
+        0 => 0.0,
+        _ => length as f64 * (pool as f64).log2(),
     }
-    for _ in 0..(length - password.len() as u8) {
-        let c = chars
-            .choose(&mut rng)
-            .expect("chars won't be empty in this context");
-        password.push(*c);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ALL: CharClasses = CharClasses {
+        upper: true,
+        lower: true,
+        number: true,
+        symbol: true,
+    };
+    const NONE: CharClasses = CharClasses {
+        upper: false,
+        lower: false,
+        number: false,
+        symbol: false,
+    };
+
+    #[test]
+    fn test_generate_password() -> Result<()> {
+        let policy = PasswordPolicy::default();
+        let password = process_genpass(16, ALL, &policy)?;
+        assert_eq!(password.len(), 16);
+        assert!(password.bytes().any(|c| SYMBOL.contains(&c)));
+
+        let password = process_genpass(
+            4,
+            CharClasses {
+                number: true,
+                ..NONE
+            },
+            &policy,
+        )?;
+        assert!(password.bytes().all(|c| NUMBER.contains(&c)));
+        let password = process_genpass(
+            8,
+            CharClasses {
+                symbol: false,
+                ..ALL
+            },
+            &policy,
+        )?;
+        assert!(!password.bytes().any(|c| SYMBOL.contains(&c)));
+
+        assert!(process_genpass(3, ALL, &policy).is_err());
+        assert!(process_genpass(16, NONE, &policy).is_err());
+        assert!(process_genpass(
+            0,
+            CharClasses {
+                upper: true,
+                ..NONE
+            },
+            &policy
+        )
+        .is_err());
+        Ok(())
     }
 
-    password.shuffle(&mut rng);
+    #[test]
+    fn test_generate_password_with_policy() -> Result<()> {
+        let policy: PasswordPolicy = serde_yaml::from_str(
+            "min_length: 12\nmin_uppercase: 3\nmin_number: 4\nmin_symbol: 2\nsymbols: \"-+\"\nmax_repeat: 1\nforbidden: [ab, \"23\"]\n",
+        )?;
+        for _ in 0..100 {
+            let password = process_genpass(12, ALL, &policy)?;
+            assert!(policy.violations(&password).is_empty(), "{}", password);
+            assert!(password
+                .bytes()
+                .all(|c| c.is_ascii_alphanumeric() || b"-+".contains(&c)));
+        }
+        // 长度不满足策略或者策略要求的字符被禁用
+        assert!(process_genpass(10, ALL, &policy).is_err());
+        assert!(process_genpass(
+            12,
+            CharClasses {
+                symbol: false,
+                ..ALL
+            },
+            &policy
+        )
+        .is_err());
+        assert!(process_genpass(8, ALL, &policy).is_err());
+        // 不可能满足的策略
+        let policy: PasswordPolicy = serde_yaml::from_str(
+            "forbidden: [\"2\", \"3\", \"4\", \"5\", \"6\", \"7\", \"8\", \"9\"]",
+        )?;
+        assert!(process_genpass(
+            4,
+            CharClasses {
+                number: true,
+                ..NONE
+            },
+            &policy
+        )
+        .is_err());
+        Ok(())
+    }
 
-    // String原生支持{} 不需要{:?}
-    let password = String::from_utf8(password)?;
+    #[test]
+    fn test_password_info() {
+        // 8 个数字, 每个字符 3 bits
+        let policy = PasswordPolicy::default();
+        assert_eq!(
+            password_entropy(
+                4,
+                CharClasses {
+                    number: true,
+                    ..NONE
+                },
+                &policy
+            ),
+            12.0
+        );
+        assert_eq!(password_entropy(4, NONE, &policy), 0.0);
 
-    Ok(password)
+        let info = PasswordInfo::new("password".to_string(), 12.0);
+        assert_eq!(info.length, 8);
+        assert_eq!(info.zxcvbn_score, 0);
+        assert_eq!(info.crack_time, "less than a second");
+    }
 }
@@ -0,0 +1,148 @@
+use std::collections::HashSet;
+
+use anyhow::Result;
+use rand::{seq::SliceRandom, Rng};
+
+use crate::{cli::PassphraseOpts, get_content};
+
+use super::gen_pass::NUMBER;
+
+// 内置的词表, 每行一个单词
+const WORDLIST: &str = include_str!("../../assets/wordlist.txt");
+
+/// 生成的口令和它的熵 (按词表大小计算, 不依赖 zxcvbn 的估计)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Passphrase {
+    pub phrase: String,
+    pub entropy_bits: f64,
+}
+
+pub fn process_genphrase(opts: &PassphraseOpts) -> Result<Passphrase> {
+    let words = load_wordlist(opts.wordlist.as_deref())?;
+    let words = words.iter().map(String::as_str).collect::<Vec<_>>();
+    generate_passphrase(&mut rand::thread_rng(), &words, opts)
+}
+
+/**
+ * 从词表中随机选取 opts.words 个单词, --capitalize 时每个单词首字母大写,
+ * --digit 时在随机一个单词后面加一个数字
+ */
+pub fn generate_passphrase<R: Rng + ?Sized>(
+    rng: &mut R,
+    wordlist: &[&str],
+    opts: &PassphraseOpts,
+) -> Result<Passphrase> {
+    let count = opts.words.unwrap_or_default() as usize;
+    if count == 0 {
+        anyhow::bail!("passphrase must contain at least one word");
+    }
+    if wordlist.len() < 2 {
+        anyhow::bail!("wordlist must contain at least 2 distinct words");
+    }
+    let mut words = (0..count)
+        .map(|_| {
+            let word = *wordlist.choose(rng).expect("wordlist won't be empty");
+            match opts.capitalize {
+                true => capitalize(word),
+                false => word.to_string(),
+            }
+        })
+        .collect::<Vec<_>>();
+    let mut entropy_bits = count as f64 * (wordlist.len() as f64).log2();
+    if opts.digit {
+        let digit = *NUMBER.choose(rng).expect("NUMBER won't be empty") as char;
+        let i = rng.gen_range(0..count);
+        words[i].push(digit);
+        entropy_bits += (count as f64 * NUMBER.len() as f64).log2();
+    }
+    Ok(Passphrase {
+        phrase: words.join(&opts.separator),
+        entropy_bits,
+    })
+}
+
+/// 支持每行一个单词, 以及 EFF 词表 "11111\tabacus" 这种前面带骰子编号的格式; 重复的单词只保留一个
+pub fn load_wordlist(path: Option<&str>) -> Result<Vec<String>> {
+    let content = match path {
+        Some(path) => String::from_utf8(get_content(path)?)?,
+        None => WORDLIST.to_string(),
+    };
+    let mut seen = HashSet::new();
+    let words = content
+        .lines()
+        .filter_map(|line| line.split_whitespace().last())
+        .filter(|word| !word.starts_with('#') && seen.insert(word.to_string()))
+        .map(String::from)
+        .collect::<Vec<_>>();
+    Ok(words)
+}
+
+fn capitalize(word: &str) -> String {
+    let mut chars = word.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opts(words: u8) -> PassphraseOpts {
+        PassphraseOpts {
+            words: Some(words),
+            separator: "-".to_string(),
+            wordlist: None,
+            capitalize: false,
+            digit: false,
+        }
+    }
+
+    #[test]
+    fn test_generate_passphrase() -> Result<()> {
+        let ret = process_genphrase(&opts(6))?;
+        assert_eq!(ret.phrase.split('-').count(), 6);
+        // 每个单词的熵为 log2(词表大小), 与内置的是哪份词表无关
+        let size = load_wordlist(None)?.len() as f64;
+        assert!((ret.entropy_bits - 6.0 * size.log2()).abs() < 1e-9);
+
+        let opts = PassphraseOpts {
+            separator: " ".to_string(),
+            capitalize: true,
+            digit: true,
+            ..opts(4)
+        };
+        let ret = generate_passphrase(&mut rand::thread_rng(), &["abacus", "zoo"], &opts)?;
+        let words = ret.phrase.split(' ').collect::<Vec<_>>();
+        assert_eq!(words.len(), 4);
+        assert!(words
+            .iter()
+            .all(|w| w.starts_with('A') || w.starts_with('Z')));
+        assert_eq!(
+            words
+                .iter()
+                .filter(|w| w.ends_with(char::is_numeric))
+                .count(),
+            1
+        );
+        assert!((ret.entropy_bits - (4.0 + 32f64.log2())).abs() < 1e-9);
+
+        let rng = &mut rand::thread_rng();
+        assert!(generate_passphrase(rng, &["abacus"], &opts).is_err());
+        assert!(generate_passphrase(rng, &["abacus", "zoo"], &self::opts(0)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_load_wordlist() -> Result<()> {
+        let builtin = load_wordlist(None)?;
+        assert_eq!(builtin.len(), WORDLIST.lines().count());
+        let dir = tempfile::tempdir()?;
+        let path = dir.path().join("eff.txt");
+        std::fs::write(&path, "11111\tabacus\n11112\tabdomen\n\n11113\tabacus\n")?;
+        let words = load_wordlist(path.to_str())?;
+        assert_eq!(words, ["abacus", "abdomen"]);
+        Ok(())
+    }
+}
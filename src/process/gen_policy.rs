@@ -0,0 +1,229 @@
+use anyhow::Result;
+use serde::Deserialize;
+
+use crate::get_content;
+
+use super::gen_pass::{CharClasses, DEFAULT_LENGTH, SYMBOL};
+
+/**
+ * 密码策略, 从 yaml 文件中读取, 例如:
+ * min_length: 12
+ * min_uppercase: 2
+ * min_number: 2
+ * symbols: "!@#$%"
+ * max_repeat: 2
+ * forbidden: [password, admin]
+ */
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasswordPolicy {
+    pub min_length: u8,
+    pub max_length: Option<u8>,
+    // 每种字符最少出现的次数
+    pub min_uppercase: u8,
+    pub min_lowercase: u8,
+    pub min_number: u8,
+    pub min_symbol: u8,
+    // 允许使用的符号, 为空时不允许任何符号
+    pub symbols: String,
+    // 同一个字符最多连续出现的次数
+    pub max_repeat: Option<u8>,
+    // 不能包含的子串, 不区分大小写
+    pub forbidden: Vec<String>,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> Self {
+        Self {
+            min_length: 0,
+            max_length: None,
+            min_uppercase: 0,
+            min_lowercase: 0,
+            min_number: 0,
+            min_symbol: 0,
+            symbols: String::from_utf8_lossy(SYMBOL).into_owned(),
+            max_repeat: None,
+            forbidden: Vec::new(),
+        }
+    }
+}
+
+impl PasswordPolicy {
+    pub fn load(path: &str) -> Result<Self> {
+        let content = get_content(path)?;
+        let policy: Self = serde_yaml::from_slice(&content)?;
+        policy.validate()?;
+        Ok(policy)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if let Some(c) = self.symbols.chars().find(|c| !c.is_ascii_punctuation()) {
+            anyhow::bail!("policy symbols must be ascii punctuation, found {:?}", c);
+        }
+        if self.max_repeat == Some(0) {
+            anyhow::bail!("policy max_repeat must be at least 1");
+        }
+        if let Some(max) = self.max_length {
+            if max < self.min_length {
+                anyhow::bail!(
+                    "policy max_length {} is less than min_length {}",
+                    max,
+                    self.min_length
+                );
+            }
+        }
+        if self.forbidden.iter().any(|s| s.is_empty()) {
+            anyhow::bail!("policy forbidden substrings must not be empty");
+        }
+        Ok(())
+    }
+
+    /**
+     * 没有用 -l 指定长度时, 把默认长度调整到策略和启用的字符种类允许的范围内;
+     * 指定的长度原样返回, 冲突时由生成密码时报错
+     */
+    pub fn password_length(&self, length: Option<u8>, classes: CharClasses) -> Result<u8> {
+        if let Some(length) = length {
+            return Ok(length);
+        }
+        let required = classes.required_length(self);
+        let max = self.max_length.unwrap_or(u8::MAX);
+        if required > max as usize {
+            anyhow::bail!(
+                "policy max_length {} is too short, the enabled character classes need at least {} characters",
+                max,
+                required
+            );
+        }
+        Ok(DEFAULT_LENGTH
+            .max(self.min_length)
+            .max(required as u8)
+            .min(max))
+    }
+
+    /// 返回密码违反的所有规则, 为空表示满足策略
+    pub fn violations(&self, password: &str) -> Vec<String> {
+        let mut violations = Vec::new();
+        let length = password.chars().count();
+        if length < self.min_length as usize {
+            violations.push(format!("shorter than {} characters", self.min_length));
+        }
+        if let Some(max) = self.max_length {
+            if length > max as usize {
+                violations.push(format!("longer than {} characters", max));
+            }
+        }
+
+        let count = |f: fn(&char) -> bool| password.chars().filter(f).count();
+        let symbol = |c: &char| !c.is_ascii_alphanumeric();
+        let classes = [
+            (
+                "uppercase letters",
+                self.min_uppercase,
+                count(char::is_ascii_uppercase),
+            ),
+            (
+                "lowercase letters",
+                self.min_lowercase,
+                count(char::is_ascii_lowercase),
+            ),
+            ("numbers", self.min_number, count(char::is_ascii_digit)),
+            ("symbols", self.min_symbol, count(symbol)),
+        ];
+        for (name, min, found) in classes {
+            if found < min as usize {
+                violations.push(format!("needs at least {} {}, found {}", min, name, found));
+            }
+        }
+
+        let mut disallowed = String::new();
+        for c in password.chars() {
+            if symbol(&c) && !self.symbols.contains(c) && !disallowed.contains(c) {
+                disallowed.push(c);
+            }
+        }
+        if !disallowed.is_empty() {
+            violations.push(format!("contains disallowed symbols {:?}", disallowed));
+        }
+
+        if let Some(max) = self.max_repeat {
+            let chars = password.chars().collect::<Vec<_>>();
+            if let Some(run) = chars
+                .chunk_by(|a, b| a == b)
+                .find(|run| run.len() > max as usize)
+            {
+                violations.push(format!(
+                    "repeats {:?} {} times in a row, at most {} allowed",
+                    run[0],
+                    run.len(),
+                    max
+                ));
+            }
+        }
+
+        let lower = password.to_lowercase();
+        for s in &self.forbidden {
+            if lower.contains(&s.to_lowercase()) {
+                violations.push(format!("contains forbidden substring {:?}", s));
+            }
+        }
+        violations
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_policy_violations() -> Result<()> {
+        let policy: PasswordPolicy = serde_yaml::from_str(
+            "min_length: 10\nmin_uppercase: 2\nmin_symbol: 1\nsymbols: \"!#\"\nmax_repeat: 2\nforbidden: [Admin]\n",
+        )?;
+        assert!(policy.violations("AbcdEfgh!#2").is_empty());
+        assert_eq!(
+            policy.violations("myADMIN-aaa"),
+            [
+                "contains disallowed symbols \"-\"",
+                "repeats 'a' 3 times in a row, at most 2 allowed",
+                "contains forbidden substring \"Admin\"",
+            ]
+        );
+        assert_eq!(policy.violations("abc").len(), 3);
+
+        assert!(serde_yaml::from_str::<PasswordPolicy>("min_len: 3").is_err());
+        let policy: PasswordPolicy = serde_yaml::from_str("symbols: \"a\"")?;
+        assert!(policy.validate().is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_password_length() -> Result<()> {
+        let all = CharClasses::default();
+        let policy = PasswordPolicy::default();
+        assert_eq!(policy.password_length(None, all)?, 16);
+        assert_eq!(policy.password_length(Some(8), all)?, 8);
+        let policy: PasswordPolicy = serde_yaml::from_str("min_length: 20")?;
+        assert_eq!(policy.password_length(None, all)?, 20);
+        // 另外两种字符各至少一个
+        let policy: PasswordPolicy = serde_yaml::from_str("min_uppercase: 10\nmin_number: 10")?;
+        assert_eq!(policy.password_length(None, all)?, 22);
+        let policy: PasswordPolicy = serde_yaml::from_str("min_length: 8\nmax_length: 12")?;
+        assert_eq!(policy.password_length(None, all)?, 12);
+        // 显式指定的长度不做调整
+        assert_eq!(policy.password_length(Some(14), all)?, 14);
+
+        // 四种字符各至少一个, 最长 3 个字符的策略无法满足
+        let policy: PasswordPolicy = serde_yaml::from_str("max_length: 3")?;
+        assert!(policy.password_length(None, all).is_err());
+        let numbers = CharClasses {
+            upper: false,
+            lower: false,
+            symbol: false,
+            ..all
+        };
+        assert_eq!(policy.password_length(None, numbers)?, 3);
+        assert_eq!(crate::process_genpass(3, numbers, &policy)?.len(), 3);
+        Ok(())
+    }
+}
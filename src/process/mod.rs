@@ -1,13 +1,56 @@
 mod base64;
+mod csv_arrow;
+mod csv_cat;
 mod csv_convert;
+mod csv_diff;
+mod csv_encoding;
+mod csv_errors;
+mod csv_expr;
+mod csv_fake;
+mod csv_query;
+mod csv_schema;
+mod csv_show;
+mod csv_sort;
+mod csv_split;
+mod csv_stats;
+mod csv_validate;
+mod csv_writer;
+mod csv_xlsx;
+mod gen_check;
 mod gen_pass;
+mod gen_phrase;
+mod gen_policy;
 mod http_serve;
 mod jwt;
 mod text;
 
 pub use base64::{process_decode, process_encode};
-pub use csv_convert::process_csv;
-pub use gen_pass::process_genpass;
+pub use csv_arrow::{infer_schema, ColumnarWriter, DEFAULT_ROW_GROUP_SIZE};
+pub use csv_cat::{cat_csv, process_csv_cat};
+pub use csv_convert::{
+    build_csv_reader, build_csv_writer, column_indices, convert_csv, process_csv, process_csv_from,
+    read_csv_headers, read_csv_values, values_to_csv, RecordConverter,
+};
+pub use csv_diff::{diff_records, process_csv_diff, CellChange, CsvDiff, RowChange};
+pub use csv_encoding::{decode_reader, detect_encoding};
+pub use csv_errors::BadRows;
+pub use csv_expr::{compare_values, truthy, CompareOp, Expr};
+pub use csv_fake::{fake_csv, process_csv_fake};
+pub use csv_query::{process_csv_query, query_csv, Query, QueryResult};
+pub use csv_schema::{infer_column_types, ColumnSchema, ColumnType, CsvSchema, FakeKind, FakeSpec};
+pub use csv_show::process_csv_show;
+pub use csv_sort::{dedup_csv, process_csv_dedup, process_csv_sort, sort_csv};
+pub use csv_split::{process_csv_split, split_csv, SplitMode};
+pub use csv_stats::{process_csv_stats, profile_csv, ColumnStats, ValueCount};
+pub use csv_validate::{process_csv_validate, validate_csv, ValidationReport, Violation};
+pub use csv_writer::{cell_to_string, format_values, new_record_writer, Record, RecordWriter};
+pub use csv_xlsx::{convert_xlsx, is_workbook, read_xlsx};
+pub use gen_check::{check_passwords, process_genpass_check, CrackTimes, PasswordCheck};
+pub use gen_pass::{
+    generate_password, password_entropy, process_genpass, CharClasses, PasswordInfo,
+};
+pub use gen_phrase::{generate_passphrase, load_wordlist, process_genphrase, Passphrase};
+pub use gen_policy::PasswordPolicy;
 pub use http_serve::process_http_serve;
 pub use jwt::{process_jwt_sign, process_jwt_verify};
 pub use text::{
@@ -8,7 +8,7 @@ use std::{collections::HashMap, io::Read};
 
 use crate::{cli::TextSignFormat, get_reader};
 
-use super::process_genpass;
+use super::{process_genpass, CharClasses, PasswordPolicy};
 
 pub trait TextSigner {
     fn sign(&self, reader: &mut dyn Read) -> Result<Vec<u8>>;
@@ -124,7 +124,7 @@ impl Blake3 {
         Self { key }
     }
     pub fn generate() -> Result<HashMap<&'static str, Vec<u8>>> {
-        let key = process_genpass(32, true, true, true, true)?;
+        let key = process_genpass(32, CharClasses::default(), &PasswordPolicy::default())?;
         let mut map = HashMap::new();
         map.insert("blake3.txt", key.as_bytes().to_vec());
         Ok(map)
@@ -1,7 +1,8 @@
 use anyhow::Result;
 use std::{
     fs::File,
-    io::{self, Read},
+    io::{self, BufWriter, Read, Write},
+    path::Path,
 };
 
 pub fn get_reader(input: &str) -> Result<Box<dyn Read>> {
@@ -19,3 +20,78 @@ pub fn get_content(input: &str) -> Result<Vec<u8>> {
     reader.read_to_end(&mut buf)?;
     Ok(buf)
 }
+
+/// 与 get_reader 对应, "-" 表示写到 stdout
+pub fn get_writer(output: &str) -> Result<Box<dyn Write + Send>> {
+    let writer: Box<dyn Write + Send> = if output == "-" {
+        Box::new(BufWriter::new(io::stdout()))
+    } else {
+        Box::new(BufWriter::new(File::create(output)?))
+    };
+    Ok(writer)
+}
+
+/**
+ * 先写到输出文件所在目录的临时文件, 成功后再 rename 为 output, 转换失败时不会留下写了一半的文件;
+ * output 已存在时需要 force 才会覆盖, "-" 表示直接写到 stdout
+ */
+pub fn write_output<F>(output: &str, force: bool, f: F) -> Result<()>
+where
+    F: FnOnce(Box<dyn Write + Send>) -> Result<()>,
+{
+    if output == "-" {
+        return f(get_writer(output)?);
+    }
+    let path = Path::new(output);
+    if path.exists() && !force {
+        anyhow::bail!("{} already exists, use --force to overwrite it", output);
+    }
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let name = path.file_name().and_then(|name| name.to_str());
+    let temp = tempfile::Builder::new()
+        .prefix(&format!(".{}.", name.unwrap_or("output")))
+        .tempfile_in(dir)?;
+    f(Box::new(BufWriter::new(temp.as_file().try_clone()?)))?;
+    temp.as_file().sync_all()?;
+    match force {
+        true => temp.persist(path)?,
+        // 避免在转换过程中被其他进程创建的文件被覆盖
+        false => temp.persist_noclobber(path)?,
+    };
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_output() -> Result<()> {
+        let dir = tempfile::tempdir()?;
+        let output = dir.path().join("output.json");
+        let output = output.to_str().unwrap();
+        write_output(output, false, |mut writer| {
+            writer.write_all(b"[]")?;
+            writer.flush()?;
+            Ok(())
+        })?;
+        assert_eq!(std::fs::read_to_string(output)?, "[]");
+
+        // 失败时保留原文件, 也不会留下临时文件
+        assert!(write_output(output, false, |_| Ok(())).is_err());
+        let ret = write_output(output, true, |mut writer| {
+            writer.write_all(b"[1,")?;
+            anyhow::bail!("invalid record")
+        });
+        assert!(ret.is_err());
+        assert_eq!(std::fs::read_to_string(output)?, "[]");
+        assert_eq!(std::fs::read_dir(dir.path())?.count(), 1);
+
+        write_output(output, true, |mut writer| Ok(writer.write_all(b"[1]")?))?;
+        assert_eq!(std::fs::read_to_string(output)?, "[1]");
+        Ok(())
+    }
+}